@@ -0,0 +1,176 @@
+use super::{mix, Film, Ray, Scene, SceneItem, SceneRayHit, Vector3};
+
+use std::str::FromStr;
+
+/// Arbitrary output variables: images rendered alongside the beauty image
+/// for compositing, denoising and debugging. All but the light splits,
+/// variance and sample count describe the first surface seen through the
+/// pixel, and are zero where nothing is hit, except for the albedo, which is
+/// one there as the background shows as it is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Aov {
+  /// Reflectance of the surface, in the working space.
+  Albedo,
+  /// Shading normal in world space.
+  Normal,
+  /// Distance from the camera.
+  Depth,
+  /// World space position.
+  Position,
+  /// One more than the index of the item hit within `Scene::items`.
+  ObjectId,
+  /// One more than the index of the item's material among the distinct
+  /// materials in the scene.
+  MaterialId,
+  /// Light reaching the camera after at most one bounce, including light
+  /// seen directly.
+  Direct,
+  /// Light reaching the camera after two or more bounces.
+  Indirect,
+  /// Variance of each pixel's estimate of the image, which falls as samples
+  /// are added.
+  Variance,
+  /// Number of samples taken in each pixel, which varies with adaptive
+  /// sampling.
+  SampleCount,
+}
+
+impl Aov {
+  pub fn all() -> [Aov; 10] {
+    [
+      Aov::Albedo,
+      Aov::Normal,
+      Aov::Depth,
+      Aov::Position,
+      Aov::ObjectId,
+      Aov::MaterialId,
+      Aov::Direct,
+      Aov::Indirect,
+      Aov::Variance,
+      Aov::SampleCount,
+    ]
+  }
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Aov::Albedo => "albedo",
+      Aov::Normal => "normal",
+      Aov::Depth => "depth",
+      Aov::Position => "position",
+      Aov::ObjectId => "object-id",
+      Aov::MaterialId => "material-id",
+      Aov::Direct => "direct",
+      Aov::Indirect => "indirect",
+      Aov::Variance => "variance",
+      Aov::SampleCount => "sample-count",
+    }
+  }
+
+  /// Names of the channels the AOV fills, from the first component of
+  /// each pixel on.
+  pub fn channels(self) -> &'static [&'static str] {
+    match self {
+      Aov::Albedo | Aov::Direct | Aov::Indirect | Aov::Variance => &["R", "G", "B"],
+      Aov::Normal | Aov::Position => &["X", "Y", "Z"],
+      Aov::Depth => &["Z"],
+      Aov::ObjectId | Aov::MaterialId => &["id"],
+      Aov::SampleCount => &["count"],
+    }
+  }
+
+  /// Whether the AOV holds colours in the working space, rather than data
+  /// that must not be converted or tone mapped.
+  pub fn is_color(self) -> bool {
+    matches!(self, Aov::Albedo | Aov::Direct | Aov::Indirect)
+  }
+
+  /// Whether samples are averaged over the pixel. IDs are not, as the
+  /// average of two IDs means nothing; they come from the first sample.
+  /// Neither is the sample count, which is a total.
+  pub fn is_averaged(self) -> bool {
+    !matches!(self, Aov::ObjectId | Aov::MaterialId | Aov::SampleCount)
+  }
+
+  /// Whether the AOV needs the integrator to split its light up.
+  pub fn is_light(self) -> bool {
+    matches!(self, Aov::Direct | Aov::Indirect)
+  }
+
+  /// Value of a first hit AOV for camera ray `r`, which found `hit`. Zero
+  /// for the other AOVs.
+  pub fn first_hit(self, scene: &Scene, r: Ray, hit: Option<&SceneRayHit>) -> Vector3 {
+    let hit = match hit {
+      Some(hit) => hit,
+      None if self == Aov::Albedo => return Vector3::one(),
+      None => return Vector3::zero(),
+    };
+
+    match self {
+      Aov::Albedo => scene.items[hit.index].get_material().albedo(),
+      Aov::Normal => hit.hit.normal,
+      Aov::Depth => Vector3::one() * (hit.hit.t * r.direction.length()),
+      Aov::Position => hit.hit.point,
+      Aov::ObjectId => Vector3::one() * (hit.index + 1) as f32,
+      Aov::MaterialId => Vector3::one() * (scene.material_id(hit.index) + 1) as f32,
+      Aov::Direct | Aov::Indirect | Aov::Variance | Aov::SampleCount => Vector3::zero(),
+    }
+  }
+
+  /// Data AOVs brought into [0, 1] for viewing in 8-bit images: normals are
+  /// mapped from [-1, 1], depth to 1 / (1 + depth), each ID gets its own
+  /// colour and sample counts become a heat map up to the largest count.
+  /// Other AOVs are returned as they are.
+  pub fn visualize(self, film: &Film) -> Film {
+    let most = film.pixels.iter().fold(0.0f32, |most, c| most.max(c.x()));
+    let view = |c: Vector3| match self {
+      Aov::Normal => 0.5 * (c + Vector3::one()),
+      Aov::Depth if c.x() > 0.0 => Vector3::one() / (1.0 + c.x()),
+      Aov::Depth => Vector3::zero(),
+      Aov::ObjectId | Aov::MaterialId if c.x() > 0.0 => {
+        let bits = mix(c.x() as u64);
+        let channel = |shift: u64| ((bits >> shift) & 0xff) as f32 / 255.0;
+        Vector3::new(channel(0), channel(8), channel(16))
+      },
+      Aov::SampleCount if most > 0.0 => heat(c.x() / most),
+      _ => c,
+    };
+
+    Film {
+      width: film.width,
+      height: film.height,
+      space: film.space,
+      pixels: film.pixels.iter().map(|c| view(*c)).collect(),
+    }
+  }
+}
+
+/// Colour for `t` in [0, 1] on a scale from black through blue, cyan, green
+/// and yellow to red.
+fn heat(t: f32) -> Vector3 {
+  let stops = [
+    Vector3::zero(),
+    Vector3::new(0.0, 0.0, 1.0),
+    Vector3::new(0.0, 1.0, 1.0),
+    Vector3::new(0.0, 1.0, 0.0),
+    Vector3::new(1.0, 1.0, 0.0),
+    Vector3::new(1.0, 0.0, 0.0),
+  ];
+  let position = t.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+  let i = (position as usize).min(stops.len() - 2);
+  Vector3::lerp(position - i as f32, stops[i], stops[i + 1])
+}
+
+impl FromStr for Aov {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Aov, String> {
+    Aov::all()
+      .iter()
+      .find(|aov| aov.name() == s)
+      .cloned()
+      .ok_or_else(|| {
+        let names: Vec<_> = Aov::all().iter().map(|aov| aov.name()).collect();
+        format!("unknown AOV '{}', expected one of {}", s, names.join(", "))
+      })
+  }
+}
@@ -0,0 +1,72 @@
+use super::{parse_color, Matrix3, Vector3};
+
+use std::str::FromStr;
+
+/// Radiance arriving from directions in which a ray escapes the scene.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Background {
+  Black,
+  Constant(Vector3),
+  /// Blends from `bottom` straight down to `top` straight up.
+  Gradient {
+    bottom: Vector3,
+    top: Vector3,
+  },
+}
+
+impl Background {
+  /// The white to light blue sky from the book.
+  pub fn sky() -> Background {
+    Background::Gradient {
+      bottom: Vector3::one(),
+      top: Vector3::new(0.5, 0.7, 1.0),
+    }
+  }
+
+  /// The same background with its colours transformed by `m`.
+  pub fn transform_colors(self, m: Matrix3) -> Background {
+    match self {
+      Background::Black => Background::Black,
+      Background::Constant(color) => Background::Constant(m * color),
+      Background::Gradient { bottom, top } => Background::Gradient {
+        bottom: m * bottom,
+        top: m * top,
+      },
+    }
+  }
+
+  pub fn color(&self, direction: Vector3) -> Vector3 {
+    match self {
+      Background::Black => Vector3::zero(),
+      Background::Constant(color) => *color,
+      Background::Gradient { bottom, top } => {
+        let t = 0.5 * (direction.as_unit().y() + 1.0);
+        Vector3::lerp(t, *bottom, *top)
+      },
+    }
+  }
+}
+
+impl FromStr for Background {
+  type Err = String;
+
+  /// Accepts `black`, `sky`, a constant colour or a `bottom:top` gradient
+  /// of two colours. Colours are linear `r,g,b` values or sRGB `#rrggbb`.
+  fn from_str(s: &str) -> Result<Background, String> {
+    match s {
+      "black" => Ok(Background::Black),
+      "sky" => Ok(Background::sky()),
+      _ => {
+        let mut colors = s.splitn(2, ':');
+        let first = parse_color(colors.next().unwrap_or(""))?;
+        match colors.next() {
+          Some(top) => Ok(Background::Gradient {
+            bottom: first,
+            top: parse_color(top)?,
+          }),
+          None => Ok(Background::Constant(first)),
+        }
+      },
+    }
+  }
+}
@@ -0,0 +1,84 @@
+use super::{MAX_ADAPTIVE_SCALE, MIN_NOISE_PASSES};
+
+use std::time::Duration;
+
+/// When a render stops adding passes. With neither a time limit nor a
+/// target noise, every pixel takes `samples` samples. With either, passes
+/// go on until the first of them is reached. A target noise alone stops
+/// after `Budget::max_passes`, in case it is never reached.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Budget {
+  /// Samples per pixel, which stratified samplers divide their strata
+  /// between, and adaptive sampling spreads over the image.
+  pub samples: usize,
+  /// Time after which no further pass is started if it would not finish in
+  /// time.
+  pub time_limit: Option<Duration>,
+  /// Root mean square noise of the image, as measured by
+  /// `Accumulator::noise`, below which the render stops.
+  pub target_noise: Option<f32>,
+}
+
+impl Budget {
+  /// A budget of exactly `samples` samples per pixel.
+  pub fn fixed(samples: usize) -> Budget {
+    Budget {
+      samples,
+      time_limit: None,
+      target_noise: None,
+    }
+  }
+
+  /// Whether `samples` is the only limit on the render.
+  pub fn is_fixed(&self) -> bool {
+    self.time_limit.is_none() && self.target_noise.is_none()
+  }
+
+  /// Passes after which a render chasing a target noise with no time limit
+  /// gives up, as fireflies can keep the noise above any target.
+  pub fn max_passes(&self) -> usize {
+    (self.samples * MAX_ADAPTIVE_SCALE).max(MIN_NOISE_PASSES)
+  }
+
+  /// Whether the render ran out of passes before reaching its target noise
+  /// of `noise`.
+  pub fn missed_target(&self, noise: f32) -> bool {
+    match self.target_noise {
+      Some(target) => noise > target,
+      None => false,
+    }
+  }
+}
+
+/// Parses a target noise, or an adaptive sampling threshold, which must be
+/// positive and finite.
+pub fn parse_target_noise(s: &str) -> Result<f32, String> {
+  match s.parse::<f32>() {
+    Ok(noise) if noise > 0.0 && noise.is_finite() => Ok(noise),
+    _ => Err(format!("invalid noise '{}', expected a positive number", s)),
+  }
+}
+
+/// Parses a duration such as `90`, `60s`, `1.5m`, `2h` or `500ms`. Plain
+/// numbers are seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+  let split = s.find(|c: char| c.is_alphabetic()).unwrap_or(s.len());
+  let (number, unit) = s.split_at(split);
+  let scale = match unit {
+    "" | "s" => 1.0,
+    "ms" => 0.001,
+    "m" => 60.0,
+    "h" => 3600.0,
+    _ => {
+      return Err(format!(
+        "unknown unit in duration '{}', expected ms, s, m or h",
+        s
+      ))
+    },
+  };
+
+  match number.trim().parse::<f64>() {
+    Ok(value) if value >= 0.0 && value.is_finite() => Ok(Duration::from_secs_f64(value * scale)),
+    _ => Err(format!("invalid duration '{}'", s)),
+  }
+}
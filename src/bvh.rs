@@ -0,0 +1,251 @@
+use super::{Aabb, Bounded, Collidable, Ray, RayHit, Vector3};
+
+use std::f32;
+
+const BIN_COUNT: usize = 12;
+const MAX_LEAF_SIZE: usize = 4;
+const MAX_DEPTH: usize = 48;
+const TRAVERSAL_COST: f32 = 1.0;
+const STACK_SIZE: usize = 128;
+
+#[derive(Debug, Clone, Copy)]
+struct BvhNode {
+  bounds: Aabb,
+  /// For leaves, the first entry in `Bvh::indices`; for interior nodes, the
+  /// index of the second child. The first child always follows its parent.
+  offset: usize,
+  /// Number of items in a leaf, or zero for an interior node.
+  count: usize,
+  axis: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BuildItem {
+  index: usize,
+  bounds: Aabb,
+  centroid: Vector3,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bin {
+  bounds: Aabb,
+  count: usize,
+}
+
+/// Bounding volume hierarchy over a slice of items, built with a binned
+/// surface area heuristic. The hierarchy stores indices only, so it must be
+/// queried with the same slice it was built from.
+#[derive(Debug, Clone)]
+pub struct Bvh {
+  nodes: Vec<BvhNode>,
+  indices: Vec<usize>,
+}
+
+impl Bvh {
+  pub fn build<T: Bounded>(items: &[T]) -> Bvh {
+    let mut build_items: Vec<BuildItem> = items
+      .iter()
+      .enumerate()
+      .map(|(index, item)| {
+        let bounds = item.bounds();
+        BuildItem {
+          index,
+          bounds,
+          centroid: bounds.centroid(),
+        }
+      })
+      .collect();
+
+    let mut nodes = Vec::with_capacity(2 * items.len());
+    if !build_items.is_empty() {
+      build_node(&mut nodes, &mut build_items, 0, 0);
+    }
+
+    Bvh {
+      nodes,
+      indices: build_items.iter().map(|item| item.index).collect(),
+    }
+  }
+
+  /// Finds the closest hit along `r`, returning the index of the item hit.
+  pub fn hit<T>(&self, items: &[T], r: Ray) -> Option<(usize, RayHit)>
+  where
+    T: Collidable<Ray, Output = Option<RayHit>>,
+  {
+    self.hit_with(r, |index| items[index].hit(r))
+  }
+
+  /// Finds the closest hit along `r`, calling `hit_item` with the index of
+  /// every item whose bounds the ray passes through.
+  pub fn hit_with<F>(&self, r: Ray, mut hit_item: F) -> Option<(usize, RayHit)>
+  where
+    F: FnMut(usize) -> Option<RayHit>,
+  {
+    if self.nodes.is_empty() {
+      return None;
+    }
+
+    let mut result: Option<(usize, RayHit)> = None;
+    let mut t = f32::MAX;
+
+    let mut stack = [0usize; STACK_SIZE];
+    let mut stack_len = 1;
+    while stack_len > 0 {
+      stack_len -= 1;
+      let node_index = stack[stack_len];
+      let node = &self.nodes[node_index];
+
+      match node.bounds.hit(r) {
+        Some(t_enter) if t_enter < t => (),
+        _ => continue,
+      }
+
+      if node.count > 0 {
+        for &index in &self.indices[node.offset..node.offset + node.count] {
+          if let Some(hit) = hit_item(index) {
+            if hit.t < t {
+              t = hit.t;
+              result = Some((index, hit));
+            }
+          }
+        }
+      } else {
+        // Visit the child nearer to the ray origin first so that its hits
+        // can cull the farther one.
+        let (near, far) = if r.direction[node.axis] < 0.0 {
+          (node.offset, node_index + 1)
+        } else {
+          (node_index + 1, node.offset)
+        };
+        stack[stack_len] = far;
+        stack[stack_len + 1] = near;
+        stack_len += 2;
+      }
+    }
+
+    result
+  }
+
+  pub fn bounds(&self) -> Aabb {
+    self
+      .nodes
+      .first()
+      .map_or_else(Aabb::empty, |node| node.bounds)
+  }
+}
+
+fn bounds_of(items: &[BuildItem]) -> Aabb {
+  items
+    .iter()
+    .fold(Aabb::empty(), |bounds, item| bounds.union(item.bounds))
+}
+
+fn build_node(
+  nodes: &mut Vec<BvhNode>,
+  items: &mut [BuildItem],
+  first: usize,
+  depth: usize,
+) -> usize {
+  let bounds = bounds_of(items);
+  let node_index = nodes.len();
+  nodes.push(BvhNode {
+    bounds,
+    offset: first,
+    count: items.len(),
+    axis: 0,
+  });
+
+  if items.len() == 1 {
+    return node_index;
+  }
+
+  let centroid_bounds = items
+    .iter()
+    .fold(Aabb::empty(), |bounds, item| bounds.grow(item.centroid));
+  let axis = centroid_bounds.largest_axis();
+  let min = centroid_bounds.min[axis];
+  let extent = centroid_bounds.extent()[axis];
+  if extent <= 0.0 {
+    // Every centroid coincides, so no split can separate the items.
+    return node_index;
+  }
+
+  let mid = if depth < MAX_DEPTH {
+    let bin_of = |item: &BuildItem| {
+      let b = ((item.centroid[axis] - min) / extent * BIN_COUNT as f32) as usize;
+      b.min(BIN_COUNT - 1)
+    };
+
+    let mut bins = [Bin {
+      bounds: Aabb::empty(),
+      count: 0,
+    }; BIN_COUNT];
+    for item in items.iter() {
+      let bin = &mut bins[bin_of(item)];
+      bin.bounds = bin.bounds.union(item.bounds);
+      bin.count += 1;
+    }
+
+    // Sweep from the right to get the cost of everything beyond each split,
+    // then from the left to find the cheapest split.
+    let mut right_areas = [0.0f32; BIN_COUNT];
+    let mut right_counts = [0usize; BIN_COUNT];
+    let mut right_bounds = Aabb::empty();
+    let mut right_count = 0;
+    for split in (1..BIN_COUNT).rev() {
+      right_bounds = right_bounds.union(bins[split].bounds);
+      right_count += bins[split].count;
+      right_areas[split] = right_bounds.surface_area();
+      right_counts[split] = right_count;
+    }
+
+    let mut best_split = 0;
+    let mut best_cost = f32::MAX;
+    let mut left_bounds = Aabb::empty();
+    let mut left_count = 0;
+    for split in 1..BIN_COUNT {
+      left_bounds = left_bounds.union(bins[split - 1].bounds);
+      left_count += bins[split - 1].count;
+      if left_count == 0 || right_counts[split] == 0 {
+        continue;
+      }
+
+      let cost = left_bounds.surface_area() * left_count as f32
+        + right_areas[split] * right_counts[split] as f32;
+      if cost < best_cost {
+        best_cost = cost;
+        best_split = split;
+      }
+    }
+
+    let split_cost = TRAVERSAL_COST + best_cost / bounds.surface_area().max(f32::EPSILON);
+    if best_split == 0 || (split_cost >= items.len() as f32 && items.len() <= MAX_LEAF_SIZE) {
+      return node_index;
+    }
+
+    let mut mid = 0;
+    for i in 0..items.len() {
+      if bin_of(&items[i]) < best_split {
+        items.swap(i, mid);
+        mid += 1;
+      }
+    }
+    mid
+  } else {
+    // Past the depth limit fall back to median splits, which keeps the tree
+    // shallow enough for the fixed traversal stack.
+    items.sort_by(|a, b| a.centroid[axis].total_cmp(&b.centroid[axis]));
+    items.len() / 2
+  };
+
+  let (left, right) = items.split_at_mut(mid);
+  build_node(nodes, left, first, depth + 1);
+  let right_index = build_node(nodes, right, first + mid, depth + 1);
+
+  let node = &mut nodes[node_index];
+  node.offset = right_index;
+  node.count = 0;
+  node.axis = axis;
+
+  node_index
+}
@@ -1,6 +1,178 @@
-use super::{Ray, Vector3};
+use super::{Collidable, Ray, Sampler, Scene, Vector3};
 
-use std::f32;
+use std::{f32, fmt, str::FromStr};
+
+/// The point in the image that autofocus focuses on.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Autofocus {
+  Center,
+  /// A pixel, counted from the top left corner.
+  Pixel(usize, usize),
+}
+
+impl FromStr for Autofocus {
+  type Err = String;
+
+  /// Accepts `center` or the `x,y` of a pixel.
+  fn from_str(s: &str) -> Result<Autofocus, String> {
+    if s == "center" {
+      return Ok(Autofocus::Center);
+    }
+
+    let error = || format!("invalid autofocus point '{}', expected center or x,y", s);
+    let coordinates = s
+      .split(',')
+      .map(|c| c.trim().parse::<usize>())
+      .collect::<Result<Vec<_>, _>>()
+      .map_err(|_| error())?;
+    match coordinates[..] {
+      [x, y] => Ok(Autofocus::Pixel(x, y)),
+      _ => Err(error()),
+    }
+  }
+}
+
+/// Where a camera is and how its lens is set, independent of the image's
+/// aspect ratio. Settings left out of a scene file take their defaults.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraSettings {
+  pub eye: Vector3,
+  pub target: Vector3,
+  pub up: Vector3,
+  /// Vertical field of view in degrees.
+  pub fov: f32,
+  /// Diameter of the lens; zero for a pinhole.
+  pub aperture: f32,
+  /// Distance from the eye of the plane in focus.
+  pub focus_distance: f32,
+  /// Where to find `focus_distance` by casting a ray into the scene,
+  /// instead of taking it as given.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub autofocus: Option<Autofocus>,
+}
+
+impl CameraSettings {
+  /// Checks that the settings describe a camera that can be built: one
+  /// looking somewhere, with `up` not along the line of sight, a field of
+  /// view that opens less than straight sideways and a lens in focus at
+  /// some distance in front.
+  pub fn validate(&self) -> Result<(), String> {
+    let forward = self.target - self.eye;
+    let distance = forward.length();
+    if !(distance > 0.0 && distance.is_finite()) {
+      return Err(format!(
+        "invalid camera target {} for an eye at {}, expected a different point",
+        self.target, self.eye
+      ));
+    }
+    // The sine of the angle between `up` and the line of sight, which the
+    // camera's axes are found from.
+    let sine = self.up.cross(forward).length() / (self.up.length() * distance);
+    if !(sine > 1e-3 && sine.is_finite()) {
+      return Err(format!(
+        "invalid camera up {}, expected a direction not along the line of sight {}",
+        self.up, forward
+      ));
+    }
+    if !(self.fov > 0.0 && self.fov < 180.0) {
+      return Err(format!(
+        "invalid field of view {}, expected degrees between 0 and 180",
+        self.fov
+      ));
+    }
+    if !(self.aperture >= 0.0 && self.aperture.is_finite()) {
+      return Err(format!(
+        "invalid aperture {}, expected a number that is not negative",
+        self.aperture
+      ));
+    }
+    if !(self.focus_distance > 0.0 && self.focus_distance.is_finite()) {
+      return Err(format!(
+        "invalid focus distance {}, expected a positive number",
+        self.focus_distance
+      ));
+    }
+    Ok(())
+  }
+
+  /// A camera for an image `aspect` times as wide as it is high.
+  pub fn build(&self, aspect: f32) -> Camera {
+    Camera::new(
+      self.eye,
+      self.target,
+      self.up,
+      self.fov,
+      aspect,
+      self.aperture,
+      self.focus_distance,
+    )
+  }
+
+  /// Focuses on the surface seen at the autofocus point of a `width` by
+  /// `height` image, setting the focus distance to its depth along the line
+  /// of sight. Returns false if no surface is seen there, leaving the focus
+  /// distance alone. Either way, the point is cleared, so that the settings
+  /// describe the camera in full.
+  pub fn autofocus(&mut self, scene: &Scene, width: usize, height: usize) -> Result<bool, String> {
+    let (u, v) = match self.autofocus.take() {
+      None => return Ok(true),
+      Some(Autofocus::Center) => (0.5, 0.5),
+      // Rows are counted from the top, as in `Scene::render_pass`.
+      Some(Autofocus::Pixel(x, y)) if x < width && y < height => (
+        (x as f32 + 0.5) / width as f32,
+        ((height - y) as f32 + 0.5) / height as f32,
+      ),
+      Some(Autofocus::Pixel(x, y)) => {
+        return Err(format!(
+          "autofocus pixel {},{} is outside the {}x{} image",
+          x, y, width, height
+        ))
+      },
+    };
+
+    let ray = self.build(width as f32 / height as f32).pinhole_ray(u, v);
+    match scene.hit(ray) {
+      Some(hit) => {
+        let forward = (self.target - self.eye).as_unit();
+        self.focus_distance = (hit.hit.point - self.eye).dot(forward);
+        Ok(true)
+      },
+      None => Ok(false),
+    }
+  }
+}
+
+impl fmt::Display for CameraSettings {
+  /// Writes the settings as they would appear in a scene file.
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "(eye: {}, target: {}, up: {}, fov: {}, aperture: {}, focus_distance: {}",
+      self.eye, self.target, self.up, self.fov, self.aperture, self.focus_distance
+    )?;
+    match self.autofocus {
+      Some(Autofocus::Center) => write!(f, ", autofocus: Center)"),
+      Some(Autofocus::Pixel(x, y)) => write!(f, ", autofocus: Pixel({}, {}))", x, y),
+      None => write!(f, ")"),
+    }
+  }
+}
+
+impl Default for CameraSettings {
+  /// The view of `Scene::random` from the book's cover.
+  fn default() -> CameraSettings {
+    CameraSettings {
+      eye: Vector3::new(13.0, 2.0, 3.0),
+      target: Vector3::new(0.0, 0.0, 0.0),
+      up: Vector3::up(),
+      fov: 20.0,
+      aperture: 0.1,
+      focus_distance: 10.0,
+      autofocus: None,
+    }
+  }
+}
 
 #[derive(Copy, Clone, Debug)]
 pub struct Camera {
@@ -47,8 +219,16 @@ impl Camera {
     }
   }
 
-  pub fn get_ray(&self, u: f32, v: f32) -> Ray {
-    let rd = self.lens_radius * Vector3::random_unit_disk();
+  /// The ray through the centre of the lens towards `u`, `v` on the image.
+  pub fn pinhole_ray(&self, u: f32, v: f32) -> Ray {
+    Ray::new(
+      self.eye,
+      self.lower_left_corner + u * self.horizontal + v * self.vertical - self.eye,
+    )
+  }
+
+  pub fn get_ray(&self, u: f32, v: f32, sampler: &mut dyn Sampler) -> Ray {
+    let rd = self.lens_radius * Vector3::random_unit_disk(sampler);
     let offset = self.x * rd.x() + self.y * rd.y();
     Ray::new(
       self.eye + offset,
@@ -0,0 +1,132 @@
+use super::{Matrix3, Vector3};
+
+use std::{fmt, str::FromStr};
+
+/// Encodes linear light with the sRGB transfer function (OETF).
+pub fn srgb_encode(x: f32) -> f32 {
+  if x <= 0.003_130_8 {
+    12.92 * x
+  } else {
+    1.055 * x.powf(1.0 / 2.4) - 0.055
+  }
+}
+
+/// Decodes an sRGB encoded value back to linear light.
+pub fn srgb_decode(x: f32) -> f32 {
+  if x <= 0.040_45 {
+    x / 12.92
+  } else {
+    ((x + 0.055) / 1.055).powf(2.4)
+  }
+}
+
+/// Parses a colour given either as linear `r,g,b` values or as an sRGB
+/// encoded `#rrggbb` hex triplet, the way colour pickers show them.
+pub fn parse_color(s: &str) -> Result<Vector3, String> {
+  if !s.starts_with('#') {
+    return s.parse();
+  }
+
+  let hex = &s[1..];
+  if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+    return Err(format!("invalid hex colour '{}', expected #rrggbb", s));
+  }
+
+  let channel = |i: usize| {
+    let byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).unwrap();
+    srgb_decode(f32::from(byte) / 255.0)
+  };
+  Ok(Vector3::new(channel(0), channel(1), channel(2)))
+}
+
+/// Linear RGB colour spaces that rendering can take place in. Colours are
+/// always given in sRGB (Rec.709 primaries) and converted into the working
+/// space, and images are converted back for display. A wider gamut changes
+/// how coloured light mixes over several bounces.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorSpace {
+  /// Linear sRGB, with Rec.709 primaries and a D65 white point.
+  Srgb,
+  /// Rec.2020 primaries, also with a D65 white point.
+  Rec2020,
+  /// ACEScg (ACES AP1 primaries, D60 white point), adapted from D65 with
+  /// the Bradford transform.
+  AcesCg,
+}
+
+impl ColorSpace {
+  /// Converts linear sRGB into this space.
+  pub fn matrix_from_srgb(self) -> Matrix3 {
+    match self {
+      ColorSpace::Srgb => Matrix3::identity(),
+      ColorSpace::Rec2020 => Matrix3::new([
+        [0.627_404, 0.329_282, 0.043_314],
+        [0.069_097, 0.919_540, 0.011_361],
+        [0.016_392, 0.088_013, 0.895_595],
+      ]),
+      ColorSpace::AcesCg => Matrix3::new([
+        [0.613_097, 0.339_523, 0.047_379],
+        [0.070_194, 0.916_354, 0.013_452],
+        [0.020_616, 0.109_570, 0.869_815],
+      ]),
+    }
+  }
+
+  /// Converts from this space into linear sRGB.
+  pub fn matrix_to_srgb(self) -> Matrix3 {
+    match self {
+      ColorSpace::Srgb => Matrix3::identity(),
+      ColorSpace::Rec2020 => Matrix3::new([
+        [1.660_491, -0.587_641, -0.072_850],
+        [-0.124_550, 1.132_9, -0.008_349],
+        [-0.018_151, -0.100_579, 1.118_73],
+      ]),
+      ColorSpace::AcesCg => Matrix3::new([
+        [1.705_052, -0.621_792, -0.083_258],
+        [-0.130_257, 1.140_805, -0.010_548],
+        [-0.024_004, -0.128_969, 1.152_972],
+      ]),
+    }
+  }
+
+  /// Converts from this space into `other`.
+  pub fn matrix_to(self, other: ColorSpace) -> Matrix3 {
+    other.matrix_from_srgb() * self.matrix_to_srgb()
+  }
+
+  /// Relative luminance of `c`, a colour in this space.
+  pub fn luminance(self, c: Vector3) -> f32 {
+    let c = self.matrix_to_srgb() * c;
+    0.2126 * c.r() + 0.7152 * c.g() + 0.0722 * c.b()
+  }
+
+  pub fn name(self) -> &'static str {
+    match self {
+      ColorSpace::Srgb => "srgb",
+      ColorSpace::Rec2020 => "rec2020",
+      ColorSpace::AcesCg => "acescg",
+    }
+  }
+}
+
+impl fmt::Display for ColorSpace {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.name())
+  }
+}
+
+impl FromStr for ColorSpace {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<ColorSpace, String> {
+    match s {
+      "srgb" => Ok(ColorSpace::Srgb),
+      "rec2020" => Ok(ColorSpace::Rec2020),
+      "acescg" => Ok(ColorSpace::AcesCg),
+      _ => Err(format!(
+        "unknown colour space '{}', expected one of srgb, rec2020, acescg",
+        s
+      )),
+    }
+  }
+}
@@ -0,0 +1,76 @@
+use super::{Accumulator, Budget, Camera, Integrator, SamplerKind, Sampling, Scene, Vector3};
+
+use std::time::Instant;
+
+/// Root mean square difference between two images, over all channels.
+pub fn rmse(image: &[Vector3], reference: &[Vector3]) -> f64 {
+  let sum: f64 = image
+    .iter()
+    .zip(reference)
+    .map(|(a, b)| f64::from((*a - *b).length_squared()))
+    .sum();
+  (sum / (3 * image.len()) as f64).sqrt()
+}
+
+/// Prints the error of every sampler against a reference image at power of
+/// two sample counts up to `max_samples`. The reference is rendered with
+/// uniform random sampling at sixteen times `max_samples` and a different
+/// seed, so that it favours none of the samplers.
+pub fn print_convergence(
+  scene: &Scene,
+  camera: Camera,
+  integrator: &dyn Integrator,
+  width: usize,
+  height: usize,
+  max_samples: usize,
+  seed: u64,
+) {
+  let reference_samples = max_samples * 16;
+  println!(
+    "Rendering {}x{} reference at {}SPP...",
+    width, height, reference_samples
+  );
+  let start = Instant::now();
+  let reference_sampling = Sampling {
+    kind: SamplerKind::Random,
+    seed: !seed,
+  };
+  let mut reference = Accumulator::new(width, height, scene.space);
+  scene.render(
+    camera,
+    integrator,
+    reference_sampling,
+    Budget::fixed(reference_samples),
+    &mut reference,
+  );
+  let reference = reference.film().pixels;
+  println!(
+    "Reference completed in {:.2}s",
+    start.elapsed().as_secs_f64()
+  );
+
+  print!("{:>6}", "SPP");
+  for kind in &SamplerKind::all() {
+    print!("{:>12}", kind.name());
+  }
+  println!();
+
+  let mut samples = 1;
+  while samples <= max_samples {
+    print!("{:>6}", samples);
+    for &kind in &SamplerKind::all() {
+      let sampling = Sampling { kind, seed };
+      let mut image = Accumulator::new(width, height, scene.space);
+      scene.render(
+        camera,
+        integrator,
+        sampling,
+        Budget::fixed(samples),
+        &mut image,
+      );
+      print!("{:>12.6}", rmse(&image.film().pixels, &reference));
+    }
+    println!();
+    samples *= 2;
+  }
+}
@@ -0,0 +1,308 @@
+use super::{Aov, ColorSpace, Film, Vector3};
+
+use rayon::prelude::*;
+
+/// AOVs the denoiser is guided by. Any of them may be missing, at the cost
+/// of blurring across the edges it would have found.
+pub const DENOISE_AOVS: [Aov; 4] = [Aov::Albedo, Aov::Normal, Aov::Depth, Aov::Variance];
+
+/// Number of filter passes; each doubles the spacing of the taps.
+const ITERATIONS: u32 = 4;
+/// Difference in luminance, in standard deviations of the noise, at which
+/// weights fall off.
+const SIGMA_LUMINANCE: f32 = 3.0;
+/// Exponent applied to the cosine between normals.
+const NORMAL_POWER: i32 = 64;
+/// Relative difference in depth at which weights fall off, per pixel of
+/// tap spacing.
+const SIGMA_DEPTH: f32 = 0.02;
+/// Smallest albedo divided out, so that black surfaces keep their light.
+const MIN_ALBEDO: f32 = 0.01;
+
+/// 1D weights of the B3 spline kernel the filter spreads out.
+const KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// Guide images picked out of the AOVs.
+struct Guides<'a> {
+  /// Normals made unit length again, after averaging over the pixel.
+  normal: Option<Vec<Vector3>>,
+  depth: Option<&'a [Vector3]>,
+}
+
+/// Light being filtered, with the variance of its luminance.
+struct Signal {
+  color: Vec<Vector3>,
+  variance: Vec<f32>,
+}
+
+/// Weight of pixel `q` when filtering pixel `p` with taps `step` apart,
+/// before the kernel. `deviation` is the standard deviation of the noise in
+/// the luminance of `p`.
+fn weight(
+  luminance: &[f32],
+  deviation: f32,
+  guides: &Guides,
+  p: usize,
+  q: usize,
+  step: usize,
+) -> f32 {
+  let mut exponent = (luminance[p] - luminance[q]).abs() / (SIGMA_LUMINANCE * deviation + 1e-4);
+
+  if let Some(depth) = guides.depth {
+    let (dp, dq) = (depth[p].x(), depth[q].x());
+    // Zero depth is the background, which never mixes with surfaces.
+    if (dp > 0.0) != (dq > 0.0) {
+      return 0.0;
+    }
+    if dp > 0.0 {
+      exponent += (dp - dq).abs() / (SIGMA_DEPTH * step as f32 * dp);
+    }
+  }
+
+  let mut w = (-exponent).exp();
+  if let Some(ref normal) = guides.normal {
+    // Background pixels have no normal to compare.
+    if normal[p] != Vector3::zero() {
+      w *= normal[p].dot(normal[q]).max(0.0).powi(NORMAL_POWER);
+    }
+  }
+  w
+}
+
+/// Variance smoothed by a 3x3 Gaussian, which is steadier to judge
+/// differences by than that of a single pixel.
+fn blur_variance(variance: &[f32], width: usize, height: usize) -> Vec<f32> {
+  let mut out = vec![0.0; variance.len()];
+  for y in 0..height {
+    for x in 0..width {
+      let (mut sum, mut total) = (0.0, 0.0);
+      for dy in -1isize..=1 {
+        for dx in -1isize..=1 {
+          let (qx, qy) = (x as isize + dx, y as isize + dy);
+          if qx < 0 || qy < 0 || qx >= width as isize || qy >= height as isize {
+            continue;
+          }
+          let w = ((2 - dx.abs()) * (2 - dy.abs())) as f32;
+          sum += w * variance[qy as usize * width + qx as usize];
+          total += w;
+        }
+      }
+      out[y * width + x] = sum / total;
+    }
+  }
+  out
+}
+
+/// One pass of the filter over `signal`, with taps `step` pixels apart.
+fn filter_pass(
+  signal: &Signal,
+  space: ColorSpace,
+  guides: &Guides,
+  width: usize,
+  height: usize,
+  step: usize,
+) -> Signal {
+  let luminance: Vec<f32> = signal.color.iter().map(|c| space.luminance(*c)).collect();
+  let variance = blur_variance(&signal.variance, width, height);
+
+  let mut out: Vec<(Vector3, f32)> = vec![(Vector3::zero(), 0.0); signal.color.len()];
+  out.par_chunks_mut(width).enumerate().for_each(|(y, line)| {
+    for (x, pixel) in line.iter_mut().enumerate() {
+      let p = y * width + x;
+      let deviation = variance[p].sqrt();
+      let mut sum = Vector3::zero();
+      let mut variance_sum = 0.0;
+      let mut total = 0.0;
+
+      for (j, ky) in KERNEL.iter().enumerate() {
+        let qy = y as isize + (j as isize - 2) * step as isize;
+        if qy < 0 || qy >= height as isize {
+          continue;
+        }
+        for (i, kx) in KERNEL.iter().enumerate() {
+          let qx = x as isize + (i as isize - 2) * step as isize;
+          if qx < 0 || qx >= width as isize {
+            continue;
+          }
+
+          let q = qy as usize * width + qx as usize;
+          let w = kx * ky * weight(&luminance, deviation, guides, p, q, step);
+          sum += signal.color[q] * w;
+          variance_sum += w * w * signal.variance[q];
+          total += w;
+        }
+      }
+
+      // Pixels unlike all their neighbours, even in the centre tap when
+      // samples disagree on the normal, are left as they are.
+      *pixel = if total > 0.0 {
+        (sum / total, variance_sum / (total * total))
+      } else {
+        (signal.color[p], signal.variance[p])
+      };
+    }
+  });
+
+  Signal {
+    color: out.iter().map(|(c, _)| *c).collect(),
+    variance: out.iter().map(|(_, v)| *v).collect(),
+  }
+}
+
+/// Removes noise from `film` with an edge-avoiding à-trous wavelet filter
+/// (Dammertz et al. 2010), guided by whichever of `DENOISE_AOVS` are among
+/// `aovs`. As in SVGF (Schied et al. 2017), the variance sets how different
+/// neighbouring pixels may be before they are kept apart. Light is filtered
+/// with the albedo divided out, so that texture detail survives.
+pub fn denoise(film: &Film, aovs: &[(Aov, Film)]) -> Film {
+  let find = |wanted: Aov| {
+    aovs
+      .iter()
+      .find(|(aov, _)| *aov == wanted)
+      .map(|(_, film)| &film.pixels[..])
+  };
+  let albedo = find(Aov::Albedo);
+  let guides = Guides {
+    normal: find(Aov::Normal).map(|normal| {
+      normal
+        .iter()
+        .map(|n| {
+          if *n == Vector3::zero() {
+            *n
+          } else {
+            n.as_unit()
+          }
+        })
+        .collect()
+    }),
+    depth: find(Aov::Depth),
+  };
+  let albedo_at =
+    |i: usize| albedo.map_or(Vector3::one(), |a| a[i].max(Vector3::one() * MIN_ALBEDO));
+
+  let space = film.space;
+  let mut signal = Signal {
+    color: (0..film.pixels.len())
+      .map(|i| film.pixels[i] / albedo_at(i))
+      .collect(),
+    // Without a variance estimate nothing is taken as noise, and only the
+    // guides smooth anything.
+    variance: match find(Aov::Variance) {
+      Some(variance) => (0..film.pixels.len())
+        .map(|i| {
+          let albedo = albedo_at(i);
+          space.luminance(variance[i] / (albedo * albedo))
+        })
+        .collect(),
+      None => vec![0.0; film.pixels.len()],
+    },
+  };
+  for iteration in 0..ITERATIONS {
+    signal = filter_pass(
+      &signal,
+      space,
+      &guides,
+      film.width,
+      film.height,
+      1 << iteration,
+    );
+  }
+
+  Film {
+    width: film.width,
+    height: film.height,
+    space,
+    pixels: (0..film.pixels.len())
+      .map(|i| signal.color[i] * albedo_at(i))
+      .collect(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::{
+    rmse, Accumulator, Budget, Camera, Filter, Geometry, IntegratorKind, Material, SamplerKind,
+    Sampling, Scene, Sphere,
+  };
+  use super::*;
+
+  /// Large diffuse shapes, where the noise rather than detail dominates the
+  /// error at low sample counts.
+  fn smooth_scene() -> Scene {
+    let sphere = |center: Vector3, radius: f32, material: Material| {
+      Geometry::from_sphere(Sphere::new(center, radius), material)
+    };
+    Scene::new(vec![
+      sphere(
+        Vector3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Material::lambert(Vector3::new(0.5, 0.5, 0.5)),
+      ),
+      sphere(
+        Vector3::new(-4.0, 1.0, 0.0),
+        1.0,
+        Material::lambert(Vector3::new(0.4, 0.2, 0.1)),
+      ),
+      sphere(
+        Vector3::new(0.0, 1.0, 0.0),
+        1.0,
+        Material::lambert(Vector3::new(0.1, 0.3, 0.6)),
+      ),
+      sphere(
+        Vector3::new(4.0, 1.0, 0.0),
+        1.0,
+        Material::metal(Vector3::new(0.7, 0.6, 0.5), 0.3),
+      ),
+    ])
+  }
+
+  #[test]
+  fn denoised_image_is_closer_to_reference() {
+    let (width, height) = (64, 48);
+    let scene = smooth_scene();
+    // Looking down on the spheres, so that the horizon's edge is out of view.
+    let camera = Camera::new(
+      Vector3::new(0.0, 8.0, 10.0),
+      Vector3::new(0.0, 0.5, 0.0),
+      Vector3::up(),
+      30.0,
+      width as f32 / height as f32,
+      0.0,
+      10.0,
+    );
+    let integrator = IntegratorKind::Path.build();
+
+    let mut noisy =
+      Accumulator::with_options(width, height, scene.space, Filter::default(), &DENOISE_AOVS);
+    let sampling = Sampling {
+      kind: SamplerKind::Random,
+      seed: 0,
+    };
+    scene.render(camera, &*integrator, sampling, Budget::fixed(8), &mut noisy);
+
+    let mut reference = Accumulator::new(width, height, scene.space);
+    let sampling = Sampling {
+      kind: SamplerKind::Random,
+      seed: 1,
+    };
+    scene.render(
+      camera,
+      &*integrator,
+      sampling,
+      Budget::fixed(256),
+      &mut reference,
+    );
+    let reference = reference.film();
+
+    let film = noisy.film();
+    let denoised = denoise(&film, &noisy.aov_films());
+    let before = rmse(&film.pixels, &reference.pixels);
+    let after = rmse(&denoised.pixels, &reference.pixels);
+    assert!(
+      after < 0.7 * before,
+      "denoising took the error from {} only to {}",
+      before,
+      after
+    );
+  }
+}
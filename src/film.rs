@@ -0,0 +1,291 @@
+use super::{srgb_encode, Aov, Budget, ColorSpace, Filter, ToneMapping, Vector3};
+
+use std::time::Duration;
+
+/// Image of linear radiance per pixel, without any limit on its range.
+/// Conversion for display is a separate step, so that tone mapping and
+/// saving work from the full dynamic range.
+#[derive(Debug, Clone)]
+pub struct Film {
+  pub width: usize,
+  pub height: usize,
+  pub space: ColorSpace,
+  /// Pixels row by row, from the top of the image.
+  pub pixels: Vec<Vector3>,
+}
+
+impl Film {
+  pub fn new(width: usize, height: usize, space: ColorSpace) -> Film {
+    Film {
+      width,
+      height,
+      space,
+      pixels: vec![Vector3::zero(); width * height],
+    }
+  }
+
+  /// Display pixels packed as 0RGB: converted to sRGB primaries, tone
+  /// mapped into the displayable range and encoded with the sRGB transfer
+  /// function.
+  pub fn to_rgb24(&self, tone_mapping: ToneMapping) -> Vec<u32> {
+    let to_srgb = self.space.matrix_to_srgb();
+    self
+      .pixels
+      .iter()
+      .map(|c| {
+        // Colours outside the sRGB gamut come out with negative channels,
+        // which are clipped.
+        let c = tone_mapping.apply((to_srgb * *c).max(Vector3::zero()));
+        Vector3::new(srgb_encode(c.r()), srgb_encode(c.g()), srgb_encode(c.b())).to_rgb24()
+      })
+      .collect()
+  }
+}
+
+/// Passes always taken before noise is judged, by adaptive sampling or a
+/// target noise, so that variance is estimated from enough samples.
+pub const MIN_NOISE_PASSES: usize = 16;
+/// Most samples a pixel may take under adaptive sampling, as a multiple of
+/// the samples per pixel asked for.
+pub const MAX_ADAPTIVE_SCALE: usize = 8;
+/// Luminance below which noise is measured in absolute rather than relative
+/// terms, so that black pixels can converge.
+const MIN_NOISE_LUMINANCE: f32 = 0.05;
+
+/// Radiance samples taken within one pixel, each counted equally, for
+/// estimating how noisy the pixel is.
+#[derive(Debug, Clone, Copy)]
+pub struct PixelStats {
+  pub sum: Vector3,
+  pub squares: Vector3,
+  pub count: usize,
+}
+
+/// Running sums of the radiance samples around each pixel, weighted by the
+/// reconstruction filter and filled a pass of one sample per pixel at a
+/// time, along with those of any AOVs. AOVs take only the samples within
+/// their own pixel, each with equal weight.
+///
+/// With a `noise_threshold`, pixels stop taking samples once they and their
+/// neighbours are no noisier than it, and the samples saved go to the
+/// pixels that are.
+pub struct Accumulator {
+  pub sum: Film,
+  /// Sums of the filter weights of the samples in `sum`.
+  pub weights: Vec<f32>,
+  pub filter: Filter,
+  pub stats: Vec<PixelStats>,
+  /// AOVs rendered alongside the image.
+  pub aovs: Vec<Aov>,
+  /// Sums for every AOV of a pixel together, pixel by pixel.
+  pub aov_sums: Vec<Vector3>,
+  /// Whether each pixel takes a sample in the next pass.
+  pub active: Vec<bool>,
+  /// Noise, as measured by `noise`, below which pixels stop taking samples.
+  /// Every pixel takes the same number of samples without one.
+  pub noise_threshold: Option<f32>,
+  /// Number of passes taken so far. Pixels stopped by adaptive sampling
+  /// have taken fewer samples.
+  pub passes: usize,
+}
+
+impl Accumulator {
+  /// An accumulator box filtering within each pixel, without AOVs.
+  pub fn new(width: usize, height: usize, space: ColorSpace) -> Accumulator {
+    Accumulator::with_options(width, height, space, Filter::default(), &[])
+  }
+
+  pub fn with_options(
+    width: usize,
+    height: usize,
+    space: ColorSpace,
+    filter: Filter,
+    aovs: &[Aov],
+  ) -> Accumulator {
+    Accumulator {
+      sum: Film::new(width, height, space),
+      weights: vec![0.0; width * height],
+      filter,
+      stats: vec![
+        PixelStats {
+          sum: Vector3::zero(),
+          squares: Vector3::zero(),
+          count: 0,
+        };
+        width * height
+      ],
+      aovs: aovs.to_vec(),
+      aov_sums: vec![Vector3::zero(); width * height * aovs.len()],
+      active: vec![true; width * height],
+      noise_threshold: None,
+      passes: 0,
+    }
+  }
+
+  /// Filtered radiance arriving around each pixel so far.
+  pub fn film(&self) -> Film {
+    let pixels = self
+      .sum
+      .pixels
+      .iter()
+      .zip(&self.weights)
+      .map(|(sum, weight)| {
+        if *weight != 0.0 {
+          *sum * (1.0 / weight)
+        } else {
+          Vector3::zero()
+        }
+      })
+      .collect();
+    Film {
+      width: self.sum.width,
+      height: self.sum.height,
+      space: self.sum.space,
+      pixels,
+    }
+  }
+
+  /// Variance of each pixel's mean of the samples within it.
+  pub fn variance(&self) -> Vec<Vector3> {
+    self
+      .stats
+      .iter()
+      .map(|stats| {
+        let n = stats.count as f32;
+        // The sample variance is divided by the number of samples once more
+        // for the variance of their mean.
+        let spread = stats.squares - stats.sum * stats.sum * (1.0 / n.max(1.0));
+        spread.max(Vector3::zero()) * (1.0 / (n * (n - 1.0)).max(1.0))
+      })
+      .collect()
+  }
+
+  /// Standard error of each pixel's luminance relative to the luminance
+  /// itself, so that 0.01 means the pixel is known to within about 1%.
+  pub fn noise(&self) -> Vec<f32> {
+    let space = self.sum.space;
+    self
+      .stats
+      .iter()
+      .zip(self.variance())
+      .map(|(stats, variance)| {
+        let mean = stats.sum * (1.0 / stats.count.max(1) as f32);
+        let deviation = space.luminance(variance).max(0.0).sqrt();
+        deviation / space.luminance(mean).max(MIN_NOISE_LUMINANCE)
+      })
+      .collect()
+  }
+
+  /// Total number of samples taken over all pixels.
+  pub fn samples_taken(&self) -> usize {
+    self.stats.iter().map(|stats| stats.count).sum()
+  }
+
+  /// Root mean square of the noise of every pixel.
+  pub fn image_noise(&self) -> f32 {
+    let noise = self.noise();
+    let sum: f32 = noise.iter().map(|n| n * n).sum();
+    (sum / noise.len().max(1) as f32).sqrt()
+  }
+
+  /// Decides whether to take another pass within `budget`, `elapsed` into
+  /// the render, and which pixels take part in it. Without a noise
+  /// threshold that is every pixel. With one, pixels drop out as they
+  /// converge and the rest carry on, until every pixel has converged or,
+  /// for a fixed budget, the samples of a uniform render are spent.
+  pub fn continues(&mut self, budget: &Budget, elapsed: Duration) -> bool {
+    if let Some(limit) = budget.time_limit {
+      // Passes take about as long as the ones before, so one is only
+      // started if it should end in time.
+      let passes = self.passes as u32;
+      if passes > 0 && elapsed + elapsed / passes > limit {
+        return false;
+      }
+    }
+    if let Some(target) = budget.target_noise {
+      if self.passes >= MIN_NOISE_PASSES && self.image_noise() <= target {
+        return false;
+      }
+      if budget.time_limit.is_none() && self.passes >= budget.max_passes() {
+        return false;
+      }
+    }
+
+    let samples = budget.samples;
+    let threshold = match self.noise_threshold {
+      Some(threshold) => threshold,
+      None => return !budget.is_fixed() || self.passes < samples,
+    };
+    if self.passes < samples.min(MIN_NOISE_PASSES) {
+      return true;
+    }
+    let limit = if budget.is_fixed() {
+      if self.samples_taken() >= samples * self.stats.len() {
+        return false;
+      }
+      samples * MAX_ADAPTIVE_SCALE
+    } else {
+      usize::MAX
+    };
+
+    let (width, height) = (self.sum.width, self.sum.height);
+    let noise = self.noise();
+    for y in 0..height {
+      for x in 0..width {
+        let p = y * width + x;
+        // Neighbours count too, as the filter spreads their noise and a
+        // single pixel's estimate of its own is itself noisy.
+        let noisy = (y.saturating_sub(1)..(y + 2).min(height)).any(|qy| {
+          (x.saturating_sub(1)..(x + 2).min(width)).any(|qx| noise[qy * width + qx] > threshold)
+        });
+        self.active[p] = noisy && self.stats[p].count < limit;
+      }
+    }
+    self.active.iter().any(|active| *active)
+  }
+
+  /// Each AOV as its own image. Colour AOVs are in the working space; data
+  /// AOVs are tagged sRGB so that nothing converts them.
+  pub fn aov_films(&self) -> Vec<(Aov, Film)> {
+    self
+      .aovs
+      .iter()
+      .enumerate()
+      .map(|(i, &aov)| {
+        let space = if aov.is_color() {
+          self.sum.space
+        } else {
+          ColorSpace::Srgb
+        };
+        let pixels = match aov {
+          Aov::Variance => self.variance(),
+          Aov::SampleCount => self
+            .stats
+            .iter()
+            .map(|stats| Vector3::one() * stats.count as f32)
+            .collect(),
+          _ => {
+            let sums = self.aov_sums.iter().skip(i).step_by(self.aovs.len());
+            sums
+              .zip(&self.stats)
+              .map(|(sum, stats)| {
+                if aov.is_averaged() {
+                  *sum * (1.0 / stats.count.max(1) as f32)
+                } else {
+                  *sum
+                }
+              })
+              .collect()
+          },
+        };
+        let film = Film {
+          width: self.sum.width,
+          height: self.sum.height,
+          space,
+          pixels,
+        };
+        (aov, film)
+      })
+      .collect()
+  }
+}
@@ -0,0 +1,159 @@
+use std::{f32, fmt, str::FromStr};
+
+/// Largest filter radius accepted, in pixels. Each sample is spread over
+/// every pixel within the radius, so wider filters soon cost more than the
+/// samples themselves.
+const MAX_FILTER_RADIUS: f32 = 8.0;
+
+/// Shapes of the reconstruction filters samples are weighted by.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterKind {
+  /// Equal weight everywhere within the radius.
+  Box,
+  /// Falls off linearly to zero at the radius.
+  Tent,
+  /// A Gaussian with a standard deviation of half a pixel, shifted down to
+  /// reach zero at the radius.
+  Gaussian,
+  /// Mitchell-Netravali cubic with B = C = 1/3, stretched over the radius.
+  Mitchell,
+  /// Sinc windowed by a sinc as wide as the radius.
+  Lanczos,
+}
+
+impl FilterKind {
+  pub fn name(self) -> &'static str {
+    match self {
+      FilterKind::Box => "box",
+      FilterKind::Tent => "tent",
+      FilterKind::Gaussian => "gaussian",
+      FilterKind::Mitchell => "mitchell",
+      FilterKind::Lanczos => "lanczos",
+    }
+  }
+
+  /// Radius used when none is given.
+  pub fn default_radius(self) -> f32 {
+    match self {
+      FilterKind::Box => 0.5,
+      FilterKind::Tent => 1.0,
+      FilterKind::Gaussian => 1.5,
+      FilterKind::Mitchell => 2.0,
+      FilterKind::Lanczos => 3.0,
+    }
+  }
+}
+
+fn sinc(x: f32) -> f32 {
+  if x.abs() < 1e-5 {
+    1.0
+  } else {
+    let x = f32::consts::PI * x;
+    x.sin() / x
+  }
+}
+
+/// Mitchell-Netravali cubic over [-2, 2].
+fn mitchell(x: f32) -> f32 {
+  let (b, c) = (1.0 / 3.0, 1.0 / 3.0);
+  let x = x.abs();
+  let value = if x < 1.0 {
+    (12.0 - 9.0 * b - 6.0 * c) * x * x * x + (-18.0 + 12.0 * b + 6.0 * c) * x * x + (6.0 - 2.0 * b)
+  } else if x < 2.0 {
+    (-b - 6.0 * c) * x * x * x
+      + (6.0 * b + 30.0 * c) * x * x
+      + (-12.0 * b - 48.0 * c) * x
+      + (8.0 * b + 24.0 * c)
+  } else {
+    0.0
+  };
+  value / 6.0
+}
+
+/// Pixel reconstruction filter: how much a sample counts towards each pixel
+/// whose centre is within `radius` pixels of it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Filter {
+  pub kind: FilterKind,
+  pub radius: f32,
+}
+
+impl Filter {
+  /// Weight along one axis of a sample `x` pixels from a pixel's centre.
+  fn weight_1d(&self, x: f32) -> f32 {
+    let radius = self.radius;
+    // The box is half open, like the pixels themselves, so that with the
+    // default radius each sample lands in exactly one pixel.
+    if x < -radius || x >= radius {
+      return 0.0;
+    }
+
+    match self.kind {
+      FilterKind::Box => 1.0,
+      FilterKind::Tent => 1.0 - x.abs() / radius,
+      FilterKind::Gaussian => {
+        let gaussian = |x: f32| (-2.0 * x * x).exp();
+        (gaussian(x) - gaussian(radius)).max(0.0)
+      },
+      FilterKind::Mitchell => mitchell(2.0 * x / radius),
+      FilterKind::Lanczos => sinc(x) * sinc(x / radius),
+    }
+  }
+
+  /// Weight of a sample offset by `(x, y)` pixels from a pixel's centre.
+  pub fn weight(&self, x: f32, y: f32) -> f32 {
+    self.weight_1d(x) * self.weight_1d(y)
+  }
+}
+
+impl Default for Filter {
+  fn default() -> Filter {
+    Filter {
+      kind: FilterKind::Box,
+      radius: FilterKind::Box.default_radius(),
+    }
+  }
+}
+
+impl fmt::Display for Filter {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}:{}", self.kind.name(), self.radius)
+  }
+}
+
+impl FromStr for Filter {
+  type Err = String;
+
+  /// Accepts `box`, `tent`, `gaussian`, `mitchell` or `lanczos`, optionally
+  /// followed by `:<radius>` in pixels.
+  fn from_str(s: &str) -> Result<Filter, String> {
+    let mut parts = s.splitn(2, ':');
+    let kind = match parts.next().unwrap_or("") {
+      "box" => FilterKind::Box,
+      "tent" => FilterKind::Tent,
+      "gaussian" => FilterKind::Gaussian,
+      "mitchell" => FilterKind::Mitchell,
+      "lanczos" => FilterKind::Lanczos,
+      _ => {
+        return Err(format!(
+          "unknown filter '{}', expected one of box, tent, gaussian, mitchell, lanczos",
+          s
+        ))
+      },
+    };
+
+    let radius = match parts.next() {
+      Some(radius) => match radius.parse() {
+        Ok(radius) if radius > 0.0 && radius <= MAX_FILTER_RADIUS => radius,
+        _ => {
+          return Err(format!(
+            "invalid filter radius in '{}', expected a number of pixels above 0 and up to {}",
+            s, MAX_FILTER_RADIUS
+          ))
+        },
+      },
+      None => kind.default_radius(),
+    };
+    Ok(Filter { kind, radius })
+  }
+}
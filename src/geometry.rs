@@ -1,11 +1,21 @@
-use super::{Collidable, Material, Ray, RayHit, SceneItem, Sphere};
+use super::{
+  Aabb, Bounded, Collidable, Material, Mesh, Ray, RayHit, SceneItem, Sphere, Triangle, Vector3,
+};
 
-#[derive(Debug, Clone, Copy)]
+use std::sync::Arc;
+
+/// How far, as a fraction of the way there, a shadow ray may stop short of
+/// or past a point sampled on a triangle and still be taken to reach it.
+const SAMPLE_TOLERANCE: f32 = 1e-3;
+
+#[derive(Debug, Clone)]
 pub enum Primitive {
   Sphere(Sphere),
+  Triangle(Triangle),
+  Mesh(Arc<Mesh>),
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct Geometry {
   primitive: Primitive,
   material: Material,
@@ -18,6 +28,87 @@ impl Geometry {
       material,
     }
   }
+
+  pub fn from_triangle(triangle: Triangle, material: Material) -> Geometry {
+    Geometry {
+      primitive: Primitive::Triangle(triangle),
+      material,
+    }
+  }
+
+  pub fn primitive(&self) -> &Primitive {
+    &self.primitive
+  }
+
+  pub fn set_material(&mut self, material: Material) {
+    self.material = material;
+  }
+
+  pub fn from_mesh(mesh: Arc<Mesh>, material: Material) -> Geometry {
+    Geometry {
+      primitive: Primitive::Mesh(mesh),
+      material,
+    }
+  }
+
+  pub fn area(&self) -> f32 {
+    match self.primitive {
+      Primitive::Sphere(sphere) => sphere.area(),
+      Primitive::Triangle(triangle) => triangle.area(),
+      Primitive::Mesh(ref mesh) => mesh.area(),
+    }
+  }
+
+  /// Maps uniform `u` and `v` to a direction from `origin` towards a point on
+  /// the surface, for sampling the geometry as a light, along with the solid
+  /// angle density of choosing it. Triangles and meshes place the point at
+  /// the end of the direction.
+  pub fn sample_direction(&self, origin: Vector3, u: f32, v: f32) -> (Vector3, f32) {
+    match self.primitive {
+      Primitive::Sphere(sphere) => {
+        let direction = sphere.sample_direction(origin, u, v);
+        (direction, sphere.pdf(origin, direction))
+      },
+      Primitive::Triangle(triangle) => {
+        let direction = triangle.sample(u, v) - origin;
+        (direction, triangle.pdf(origin, direction))
+      },
+      Primitive::Mesh(ref mesh) => {
+        let (index, direction) = mesh.sample_direction(origin, u, v);
+        (direction, mesh.triangle_pdf(index, origin, direction))
+      },
+    }
+  }
+
+  /// Whether a ray along a direction from `sample_direction`, first meeting
+  /// the geometry at `t`, reaches the sampled point rather than some other
+  /// part of the geometry in front of it. Spheres are convex, so any hit
+  /// will do.
+  pub fn reaches_sample(&self, t: f32) -> bool {
+    match self.primitive {
+      Primitive::Sphere(_) => true,
+      Primitive::Triangle(_) | Primitive::Mesh(_) => (t - 1.0).abs() <= SAMPLE_TOLERANCE,
+    }
+  }
+
+  /// Solid angle density with which `sample_direction` chooses `direction`.
+  pub fn pdf(&self, origin: Vector3, direction: Vector3) -> f32 {
+    match self.primitive {
+      Primitive::Sphere(sphere) => sphere.pdf(origin, direction),
+      Primitive::Triangle(triangle) => triangle.pdf(origin, direction),
+      Primitive::Mesh(ref mesh) => mesh.pdf(origin, direction),
+    }
+  }
+}
+
+impl Bounded for Geometry {
+  fn bounds(&self) -> Aabb {
+    match self.primitive {
+      Primitive::Sphere(sphere) => sphere.bounds(),
+      Primitive::Triangle(triangle) => triangle.bounds(),
+      Primitive::Mesh(ref mesh) => mesh.bounds(),
+    }
+  }
 }
 
 impl Collidable<Ray> for Geometry {
@@ -26,6 +117,8 @@ impl Collidable<Ray> for Geometry {
   fn hit(&self, r: Ray) -> Option<RayHit> {
     match self.primitive {
       Primitive::Sphere(sphere) => sphere.hit(r),
+      Primitive::Triangle(triangle) => triangle.hit(r),
+      Primitive::Mesh(ref mesh) => mesh.hit(r),
     }
   }
 }
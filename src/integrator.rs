@@ -0,0 +1,341 @@
+use super::{
+  facing, Collidable, Material, Ray, RayHit, Sampler, Scene, SceneItem, SceneRayHit, Vector3,
+};
+
+use std::{f32, str::FromStr};
+
+/// Estimates the light arriving at the camera along a ray. `hit` is the
+/// first surface along the ray, traced by the caller so that it can be
+/// shared with the AOVs.
+pub trait Integrator: Send + Sync {
+  fn radiance(
+    &self,
+    scene: &Scene,
+    r: Ray,
+    hit: Option<&SceneRayHit>,
+    sampler: &mut dyn Sampler,
+  ) -> Vector3;
+
+  /// Radiance split into light reaching the camera after at most one
+  /// bounce and light that bounced more. Integrators that don't tell the
+  /// two apart count everything as direct.
+  fn radiance_split(
+    &self,
+    scene: &Scene,
+    r: Ray,
+    hit: Option<&SceneRayHit>,
+    sampler: &mut dyn Sampler,
+  ) -> (Vector3, Vector3) {
+    (self.radiance(scene, r, hit, sampler), Vector3::zero())
+  }
+}
+
+/// Weight for a sample taken with density `pdf` when another strategy
+/// could have produced it with density `other`.
+fn power_heuristic(pdf: f32, other: f32) -> f32 {
+  let pdf2 = pdf * pdf;
+  pdf2 / (pdf2 + other * other)
+}
+
+/// Unidirectional path tracer combining BSDF sampling with direct light
+/// sampling through multiple importance sampling.
+#[derive(Debug, Clone, Copy)]
+pub struct PathTracer {
+  pub max_depth: u32,
+}
+
+/// Radiance arriving along a ray, split by how many more bounces it took
+/// after the surface the ray hit.
+#[derive(Debug, Clone, Copy)]
+struct Arrival {
+  /// Emitted by the surface, or by the background.
+  emitted: Vector3,
+  /// Emitted by another surface and scattered once, by this one.
+  once: Vector3,
+  /// Scattered more than once.
+  more: Vector3,
+}
+
+impl Arrival {
+  fn total(&self) -> Vector3 {
+    self.emitted + self.once + self.more
+  }
+}
+
+impl PathTracer {
+  /// Radiance arriving along `r`, which first meets `hit`. `bsdf_pdf` is the
+  /// density with which the previous bounce chose `r`, or `None` for camera
+  /// rays and specular bounces, whose emission light sampling could not have
+  /// found.
+  fn trace(
+    &self,
+    scene: &Scene,
+    r: Ray,
+    hit: Option<&SceneRayHit>,
+    depth: u32,
+    bsdf_pdf: Option<f32>,
+    sampler: &mut dyn Sampler,
+  ) -> Arrival {
+    let mut arrival = Arrival {
+      emitted: Vector3::zero(),
+      once: Vector3::zero(),
+      more: Vector3::zero(),
+    };
+
+    if let Some(hit) = hit {
+      if depth < self.max_depth {
+        let material = scene.items[hit.index].get_material();
+        arrival.emitted = material.emitted();
+        if let Some(pdf) = bsdf_pdf {
+          if arrival.emitted != Vector3::zero() {
+            arrival.emitted *=
+              power_heuristic(pdf, scene.light_pdf(hit.index, r.origin, r.direction));
+          }
+        }
+
+        if let Some(bounce) = material.scatter(r, hit.hit.point, hit.hit.normal, sampler) {
+          if bounce.pdf.is_some() {
+            arrival.once = PathTracer::direct(scene, r, &hit.hit, material, sampler);
+          }
+
+          let next_hit = scene.hit(bounce.bounced);
+          let next = self.trace(
+            scene,
+            bounce.bounced,
+            next_hit.as_ref(),
+            depth + 1,
+            bounce.pdf,
+            sampler,
+          );
+          arrival.once += bounce.attenuation * next.emitted;
+          arrival.more = bounce.attenuation * (next.once + next.more);
+        }
+      }
+    } else {
+      arrival.emitted = scene.background.color(r.direction);
+    }
+
+    arrival
+  }
+
+  /// Light arriving at `hit` directly from a randomly chosen light, weighted
+  /// against the chance of `material` scattering towards it.
+  fn direct(
+    scene: &Scene,
+    r: Ray,
+    hit: &RayHit,
+    material: Material,
+    sampler: &mut dyn Sampler,
+  ) -> Vector3 {
+    match scene.sample_light(hit.point, sampler) {
+      Some(light) => {
+        let f = material.eval(r, hit.normal, light.direction);
+        let weight = power_heuristic(light.pdf, material.pdf(r, hit.normal, light.direction));
+        light.radiance * f * (weight / light.pdf)
+      },
+      None => Vector3::zero(),
+    }
+  }
+}
+
+impl Integrator for PathTracer {
+  fn radiance(
+    &self,
+    scene: &Scene,
+    r: Ray,
+    hit: Option<&SceneRayHit>,
+    sampler: &mut dyn Sampler,
+  ) -> Vector3 {
+    self.trace(scene, r, hit, 0, None, sampler).total()
+  }
+
+  fn radiance_split(
+    &self,
+    scene: &Scene,
+    r: Ray,
+    hit: Option<&SceneRayHit>,
+    sampler: &mut dyn Sampler,
+  ) -> (Vector3, Vector3) {
+    let arrival = self.trace(scene, r, hit, 0, None, sampler);
+    (arrival.emitted + arrival.once, arrival.more)
+  }
+}
+
+/// Fast preview which follows specular bounces but lights diffuse surfaces
+/// only directly, from the lights and from the background.
+#[derive(Debug, Clone, Copy)]
+pub struct Whitted {
+  pub max_depth: u32,
+}
+
+impl Whitted {
+  fn trace(
+    &self,
+    scene: &Scene,
+    r: Ray,
+    hit: Option<&SceneRayHit>,
+    depth: u32,
+    sampler: &mut dyn Sampler,
+  ) -> Vector3 {
+    let hit = match hit {
+      Some(hit) => hit,
+      None => return scene.background.color(r.direction),
+    };
+
+    let material = scene.items[hit.index].get_material();
+    let emitted = material.emitted();
+    if depth >= self.max_depth {
+      return emitted;
+    }
+
+    let (point, normal) = (hit.hit.point, hit.hit.normal);
+    match material.scatter(r, point, normal, sampler) {
+      Some(bounce) => match bounce.pdf {
+        Some(_) => {
+          let direct = scene
+            .sample_light(point, sampler)
+            .map_or(Vector3::zero(), |light| {
+              light.radiance * material.eval(r, normal, light.direction) / light.pdf
+            });
+          let sky = if scene.hit(bounce.bounced).is_none() {
+            bounce.attenuation * scene.background.color(bounce.bounced.direction)
+          } else {
+            Vector3::zero()
+          };
+
+          emitted + direct + sky
+        },
+        None => {
+          let next_hit = scene.hit(bounce.bounced);
+          let next = self.trace(scene, bounce.bounced, next_hit.as_ref(), depth + 1, sampler);
+          emitted + bounce.attenuation * next
+        },
+      },
+      None => emitted,
+    }
+  }
+}
+
+impl Integrator for Whitted {
+  fn radiance(
+    &self,
+    scene: &Scene,
+    r: Ray,
+    hit: Option<&SceneRayHit>,
+    sampler: &mut dyn Sampler,
+  ) -> Vector3 {
+    self.trace(scene, r, hit, 0, sampler)
+  }
+}
+
+/// White where the first hit is unoccluded within `distance`, black where it
+/// is occluded.
+#[derive(Debug, Clone, Copy)]
+pub struct AmbientOcclusion {
+  pub distance: f32,
+}
+
+impl Integrator for AmbientOcclusion {
+  fn radiance(
+    &self,
+    scene: &Scene,
+    r: Ray,
+    hit: Option<&SceneRayHit>,
+    sampler: &mut dyn Sampler,
+  ) -> Vector3 {
+    let hit = match hit {
+      Some(hit) => hit.hit,
+      None => return Vector3::one(),
+    };
+
+    let normal = facing(r, hit.normal);
+    let mut direction = normal + Vector3::random_unit_vector(sampler);
+    if direction.length_squared() < 1e-8 {
+      direction = normal;
+    }
+
+    let length = direction.length();
+    match scene.hit(Ray::new(hit.point, direction)) {
+      Some(ref occluder) if occluder.hit.t * length < self.distance => Vector3::zero(),
+      _ => Vector3::one(),
+    }
+  }
+}
+
+/// Shows a property of the first surface hit instead of lighting.
+#[derive(Debug, Clone, Copy)]
+pub enum DebugIntegrator {
+  /// Normals mapped from [-1, 1] to [0, 1].
+  Normals,
+  /// Distance to the camera, mapped to 1 / (1 + distance).
+  Depth,
+  Albedo,
+}
+
+impl Integrator for DebugIntegrator {
+  fn radiance(
+    &self,
+    scene: &Scene,
+    r: Ray,
+    hit: Option<&SceneRayHit>,
+    _sampler: &mut dyn Sampler,
+  ) -> Vector3 {
+    let hit = match hit {
+      Some(hit) => hit,
+      None => return Vector3::zero(),
+    };
+
+    match self {
+      DebugIntegrator::Normals => 0.5 * (hit.hit.normal + Vector3::one()),
+      DebugIntegrator::Depth => Vector3::one() / (1.0 + hit.hit.t * r.direction.length()),
+      DebugIntegrator::Albedo => scene.items[hit.index].get_material().albedo(),
+    }
+  }
+}
+
+/// Integrators selectable from the command line.
+#[derive(Debug, Clone, Copy)]
+pub enum IntegratorKind {
+  Path,
+  Whitted,
+  AmbientOcclusion { distance: f32 },
+  Debug(DebugIntegrator),
+}
+
+impl IntegratorKind {
+  pub fn build(self) -> Box<dyn Integrator> {
+    match self {
+      IntegratorKind::Path => Box::new(PathTracer { max_depth: 50 }),
+      IntegratorKind::Whitted => Box::new(Whitted { max_depth: 8 }),
+      IntegratorKind::AmbientOcclusion { distance } => Box::new(AmbientOcclusion { distance }),
+      IntegratorKind::Debug(debug) => Box::new(debug),
+    }
+  }
+}
+
+impl FromStr for IntegratorKind {
+  type Err = String;
+
+  /// Accepts `path`, `whitted`, `ao` or `ao:<distance>`, `normals`, `depth`
+  /// and `albedo`.
+  fn from_str(s: &str) -> Result<IntegratorKind, String> {
+    match s {
+      "path" => Ok(IntegratorKind::Path),
+      "whitted" => Ok(IntegratorKind::Whitted),
+      "ao" => Ok(IntegratorKind::AmbientOcclusion { distance: 1.0 }),
+      "normals" => Ok(IntegratorKind::Debug(DebugIntegrator::Normals)),
+      "depth" => Ok(IntegratorKind::Debug(DebugIntegrator::Depth)),
+      "albedo" => Ok(IntegratorKind::Debug(DebugIntegrator::Albedo)),
+      _ if s.starts_with("ao:") => match s[3..].parse::<f32>() {
+        Ok(distance) if distance > 0.0 && distance.is_finite() => {
+          Ok(IntegratorKind::AmbientOcclusion { distance })
+        },
+        _ => Err(format!("invalid ambient occlusion distance in '{}'", s)),
+      },
+      _ => Err(format!(
+        "unknown integrator '{}', expected one of path, whitted, ao, normals, depth, albedo",
+        s
+      )),
+    }
+  }
+}
@@ -1,78 +1,583 @@
 extern crate image;
-#[macro_use]
-extern crate lazy_static;
+#[cfg(feature = "window")]
 extern crate minifb;
+#[cfg(feature = "window")]
 extern crate nfd;
 extern crate rand;
 extern crate rayon;
+extern crate ron;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
 #[macro_use]
 extern crate structopt;
 
+mod aov;
+mod background;
+mod budget;
+mod bvh;
 mod camera;
+mod color;
+mod convergence;
+mod denoise;
+mod film;
+mod filter;
 mod geometry;
+mod integrator;
 mod material;
 mod math;
+mod mesh;
+mod obj;
+mod output;
+mod presets;
+mod random_spheres;
+mod sampler;
 mod scene;
+mod scene_file;
+mod tonemap;
 
+use aov::*;
+use background::*;
+use budget::*;
+use bvh::*;
 use camera::*;
+use color::*;
+use convergence::*;
+use denoise::*;
+use film::*;
+use filter::*;
 use geometry::*;
+use integrator::*;
 use material::*;
 use math::*;
+use mesh::*;
+use obj::*;
+use output::*;
+use presets::*;
+use random_spheres::*;
+use sampler::*;
 use scene::*;
+use scene_file::*;
+use tonemap::*;
+
+use std::{
+  f32, io,
+  path::PathBuf,
+  process,
+  time::{Duration, Instant},
+};
 
+#[cfg(feature = "window")]
 use std::{
-  f32,
   sync::mpsc::{channel, Receiver},
   thread,
-  time::Instant,
 };
 
-use minifb::{Key, Menu, Scale, Window, WindowOptions, MENU_KEY_ALT, MENU_KEY_CTRL};
+#[cfg(feature = "window")]
+use minifb::{Key, KeyRepeat, Menu, Scale, Window, WindowOptions, MENU_KEY_ALT, MENU_KEY_CTRL};
+#[cfg(feature = "window")]
 use nfd::Response;
-use structopt::StructOpt;
+use structopt::{clap::ArgMatches, StructOpt};
 
+#[cfg(feature = "window")]
 const SCALE: Scale = Scale::X1;
+#[cfg(feature = "window")]
 const FILE_SAVE: usize = 1;
+#[cfg(feature = "window")]
 const FILE_QUIT: usize = 2;
 
+/// Receives the number of passes completed, whether the render is finished
+/// and the image so far, from the thread rendering them. The AOVs come only
+/// with the finished image.
+#[cfg(feature = "window")]
+type RenderJob = (
+  Receiver<(usize, bool, Film, Vec<(Aov, Film)>)>,
+  thread::JoinHandle<()>,
+);
+
 #[derive(StructOpt, Debug)]
 #[structopt(name = "PathTracer", about = "A simple ray tracer.")]
 struct Args {
+  /// Renders the scene described in a RON file instead of the random
+  /// spheres. Its render settings apply where no option here is given.
+  #[structopt(long = "scene", parse(from_os_str))]
+  scene: Option<PathBuf>,
+  /// Renders one of the built-in scenes, with its own camera and settings,
+  /// instead of the random spheres. See `--list-presets`.
+  #[structopt(
+    long = "preset",
+    parse(try_from_str = "find_preset"),
+    conflicts_with = "scene"
+  )]
+  preset: Option<&'static Preset>,
+  /// Prints the names of the built-in scenes and exits.
+  #[structopt(long = "list-presets")]
+  list_presets: bool,
+  /// Sets the seed the random spheres are placed and coloured with, in
+  /// place of the book's.
+  #[structopt(long = "sphere-seed", conflicts_with = "scene")]
+  sphere_seed: Option<u64>,
+  /// Scatters the random spheres over a grid reaching this far from the
+  /// origin, 11 in the book.
+  #[structopt(long = "sphere-extent", conflicts_with = "scene")]
+  sphere_extent: Option<u32>,
+  /// Sets the average count of random spheres in each cell of their grid.
+  #[structopt(long = "sphere-density", conflicts_with = "scene")]
+  sphere_density: Option<f32>,
+  /// Sets the relative chances of `diffuse,metal,glass` random spheres, by
+  /// default `0.8,0.15,0.05`.
+  #[structopt(long = "sphere-mix", conflicts_with = "scene")]
+  sphere_mix: Option<MaterialMix>,
+  /// Sets the radius of the random spheres, or a `min,max` range to choose
+  /// from, by default 0.2.
+  #[structopt(
+    long = "sphere-radius",
+    parse(try_from_str = "parse_radius_range"),
+    conflicts_with = "scene"
+  )]
+  sphere_radius: Option<(f32, f32)>,
+  /// Leaves out the three large spheres among the random ones.
+  #[structopt(long = "no-hero-spheres", conflicts_with = "scene")]
+  no_hero_spheres: bool,
+  /// Writes the scene, with any `--obj` models, the camera and the render
+  /// settings, to a RON scene file instead of rendering it.
+  #[structopt(long = "save-scene", parse(from_os_str))]
+  save_scene: Option<PathBuf>,
+  /// Places the camera at `x,y,z`. Camera options override those of the
+  /// scene file or preset.
+  #[structopt(long = "eye", raw(allow_hyphen_values = "true"))]
+  eye: Option<Vector3>,
+  /// Points the camera at `x,y,z`.
+  #[structopt(long = "target", raw(allow_hyphen_values = "true"))]
+  target: Option<Vector3>,
+  /// Sets the `x,y,z` direction that is up in the image.
+  #[structopt(long = "up", raw(allow_hyphen_values = "true"))]
+  up: Option<Vector3>,
+  /// Sets the vertical field of view in degrees.
+  #[structopt(long = "fov")]
+  fov: Option<f32>,
+  /// Sets the diameter of the lens, or 0 for a pinhole with everything in
+  /// focus.
+  #[structopt(long = "aperture")]
+  aperture: Option<f32>,
+  /// Sets the distance from the eye of the plane in focus.
+  #[structopt(long = "focus-distance", conflicts_with = "autofocus")]
+  focus_distance: Option<f32>,
+  /// Focuses on the surface seen at the `center` of the image or at the
+  /// `x,y` pixel, counted from the top left.
+  #[structopt(long = "autofocus")]
+  autofocus: Option<Autofocus>,
   /// Sets the width of the final rendered image.
   #[structopt(short = "w", long = "width", default_value = "400")]
   width: usize,
   /// Sets the height of the final rendered image.
   #[structopt(short = "h", long = "height", default_value = "300")]
   height: usize,
-  /// Sets the count of samples taken per pixel.
+  /// Sets the count of samples taken per pixel. With `--time-limit` or
+  /// `--target-noise`, passes carry on past it.
   #[structopt(short = "s", long = "samples", default_value = "100")]
   samples: usize,
+  /// Keeps adding passes until this much time has passed, such as `90s`,
+  /// `5m` or `1h`, starting none that would not finish in time.
+  #[structopt(long = "time-limit", parse(try_from_str = "parse_duration"))]
+  time_limit: Option<Duration>,
+  /// Keeps adding passes until the root mean square of the pixels' noise,
+  /// relative to their luminance, falls below this, or until any
+  /// `--time-limit`. Without one, gives up after eight times `--samples`
+  /// passes.
+  #[structopt(long = "target-noise", parse(try_from_str = "parse_target_noise"))]
+  target_noise: Option<f32>,
+  /// Sets the seed for the random numbers used in rendering. Different seeds
+  /// give different noise; the same seed always gives the same image.
+  #[structopt(long = "seed", default_value = "0")]
+  seed: u64,
+  /// Sets how sample positions are chosen: `random`, `stratified`, `halton`
+  /// or `sobol`.
+  #[structopt(long = "sampler", default_value = "random")]
+  sampler: SamplerKind,
+  /// Prints the error of every sampler against a reference image at
+  /// increasing sample counts up to `--samples`, instead of rendering.
+  #[structopt(long = "convergence")]
+  convergence: bool,
+  /// Writes the final rendered image to the given path. `.exr`, `.hdr` and
+  /// `.pfm` files keep the full range of linear radiance; other formats are
+  /// tone mapped to 8 bits.
+  #[structopt(short = "o", long = "output")]
+  output: Option<String>,
+  /// Renders a single image to `--output` and exits without opening a window.
+  #[structopt(long = "headless", requires = "output")]
+  headless: bool,
+  /// Sets the scene background: `black`, `sky`, a constant `r,g,b` colour or
+  /// a `r,g,b:r,g,b` bottom to top gradient. Defaults to the scene file's,
+  /// or `sky`.
+  #[structopt(long = "background")]
+  background: Option<Background>,
+  /// Adds the meshes from a Wavefront OBJ file to the scene. May be repeated.
+  #[structopt(long = "obj", parse(from_os_str))]
+  obj: Vec<PathBuf>,
+  /// Sets how light is estimated: `path`, `whitted`, `ao` or
+  /// `ao:<distance>`, or one of the `normals`, `depth` and `albedo` debug
+  /// views.
+  #[structopt(long = "integrator", default_value = "path")]
+  integrator: IntegratorKind,
+  /// Sets how radiance is compressed for display: `clamp`, `reinhard`,
+  /// `reinhard-extended` or `reinhard-extended:<white>`, `aces` or `hable`.
+  /// Press T in the window to cycle through them.
+  #[structopt(long = "tone-map", default_value = "clamp")]
+  tone_map: ToneMap,
+  /// Scales radiance by a power of two before tone mapping. Press the up and
+  /// down arrows in the window to adjust it by half a stop.
+  #[structopt(
+    long = "exposure",
+    default_value = "0",
+    raw(allow_hyphen_values = "true")
+  )]
+  exposure: f32,
+  /// Sets the linear colour space light is computed in: `srgb`, `rec2020` or
+  /// `acescg`. Scene colours are always given in sRGB and converted.
+  #[structopt(long = "working-space", default_value = "srgb")]
+  working_space: ColorSpace,
+  /// Sets the precision of OpenEXR output: `half` or `float`.
+  #[structopt(long = "exr-type", default_value = "half")]
+  exr_pixel_type: ExrPixelType,
+  /// Renders an extra pass alongside the image: `albedo`, `normal`,
+  /// `depth`, `position`, `object-id`, `material-id`, the `direct` and
+  /// `indirect` lighting, the `variance` of each pixel or the `sample-count`
+  /// taken in it. May be repeated. OpenEXR output gets a layer for each;
+  /// other formats write `<name>.<aov>.<ext>` files.
+  #[structopt(long = "aov")]
+  aovs: Vec<Aov>,
+  /// Sets the filter samples are weighted by around each pixel: `box`,
+  /// `tent`, `gaussian`, `mitchell` or `lanczos`, optionally with a
+  /// `:<radius>` in pixels of at most 8.
+  #[structopt(long = "filter", default_value = "box")]
+  filter: Filter,
+  /// Removes noise from the finished image, guided by the albedo, normal
+  /// and depth AOVs.
+  #[structopt(long = "denoise")]
+  denoise: bool,
+  /// Samples adaptively: pixels stop once the standard error of their
+  /// luminance, relative to the luminance, falls below this, and the
+  /// samples saved go to noisier pixels. `--samples` sets the average.
+  #[structopt(long = "noise-threshold", parse(try_from_str = "parse_target_noise"))]
+  noise_threshold: Option<f32>,
+}
+
+/// Options configuring the random spheres, which only the random preset has.
+const SPHERE_OPTIONS: [&str; 6] = [
+  "sphere_seed",
+  "sphere_extent",
+  "sphere_density",
+  "sphere_mix",
+  "sphere_radius",
+  "no_hero_spheres",
+];
+
+impl Args {
+  /// Takes the settings in `render`, from a scene file or preset, for any
+  /// options not given in `matches`.
+  fn apply_scene_settings(&mut self, render: &RenderSettings, matches: &ArgMatches) {
+    let unset = |name: &str| matches.occurrences_of(name) == 0;
+    if let (Some(width), true) = (render.width, unset("width")) {
+      self.width = width;
+    }
+    if let (Some(height), true) = (render.height, unset("height")) {
+      self.height = height;
+    }
+    if let (Some(samples), true) = (render.samples, unset("samples")) {
+      self.samples = samples;
+    }
+    if let (Some(seed), true) = (render.seed, unset("seed")) {
+      self.seed = seed;
+    }
+  }
+
+  /// Takes the camera options given in place of those in `camera`.
+  fn apply_camera_options(&self, camera: &mut CameraSettings) {
+    camera.eye = self.eye.unwrap_or(camera.eye);
+    camera.target = self.target.unwrap_or(camera.target);
+    camera.up = self.up.unwrap_or(camera.up);
+    camera.fov = self.fov.unwrap_or(camera.fov);
+    camera.aperture = self.aperture.unwrap_or(camera.aperture);
+    if let Some(focus_distance) = self.focus_distance {
+      camera.focus_distance = focus_distance;
+      camera.autofocus = None;
+    }
+    if self.autofocus.is_some() {
+      camera.autofocus = self.autofocus;
+    }
+  }
+
+  /// Settings for the random spheres, if any of their options are given in
+  /// `matches`.
+  fn random_spheres(&self, matches: &ArgMatches) -> Result<Option<RandomSpheres>, String> {
+    if SPHERE_OPTIONS
+      .iter()
+      .all(|name| matches.occurrences_of(name) == 0)
+    {
+      return Ok(None);
+    }
+    let defaults = RandomSpheres::default();
+    let spheres = RandomSpheres {
+      seed: self.sphere_seed,
+      extent: self.sphere_extent.unwrap_or(defaults.extent),
+      density: self.sphere_density.unwrap_or(defaults.density),
+      mix: self.sphere_mix.unwrap_or(defaults.mix),
+      radius: self.sphere_radius.unwrap_or(defaults.radius),
+      heroes: !self.no_hero_spheres,
+    };
+    spheres.validate()?;
+    Ok(Some(spheres))
+  }
+
+  fn tone_mapping(&self) -> ToneMapping {
+    ToneMapping::new(self.tone_map, self.exposure)
+  }
+
+  /// AOVs to render: those asked for, and those guiding the denoiser.
+  fn render_aovs(&self) -> Vec<Aov> {
+    let mut aovs = self.aovs.clone();
+    if self.denoise {
+      for aov in &DENOISE_AOVS {
+        if !aovs.contains(aov) {
+          aovs.push(*aov);
+        }
+      }
+    }
+    aovs
+  }
+
+  fn budget(&self) -> Budget {
+    Budget {
+      samples: self.samples,
+      time_limit: self.time_limit,
+      target_noise: self.target_noise,
+    }
+  }
+
+  fn accumulator(&self, width: usize, height: usize, space: ColorSpace) -> Accumulator {
+    let mut accumulator =
+      Accumulator::with_options(width, height, space, self.filter, &self.render_aovs());
+    accumulator.noise_threshold = self.noise_threshold;
+    accumulator
+  }
 }
 
-fn save_buffer_to_path(width: u32, height: u32, buffer: &[u32], path: &str) {
-  println!("Writing {}x{} image to {}", width, height, path);
-  let mut imgbuf = image::RgbImage::new(width, height);
+/// The finished image, denoised if asked, and the `wanted` AOVs.
+fn finish(accumulator: &Accumulator, wanted: &[Aov], denoised: bool) -> (Film, Vec<(Aov, Film)>) {
+  let mut aovs = accumulator.aov_films();
+  let mut film = accumulator.film();
+  if denoised {
+    film = denoise(&film, &aovs);
+  }
+  aovs.retain(|(aov, _)| wanted.contains(aov));
+  (film, aovs)
+}
 
-  for (x, y, pixel) in imgbuf.enumerate_pixels_mut() {
-    let index = (y * width + x) as usize;
-    let value = buffer[index];
-    let r = (value >> 16) & 0xFF;
-    let g = (value >> 8) & 0xFF;
-    let b = value & 0xFF;
+fn render_headless(
+  args: &Args,
+  scene: &Scene,
+  camera: &CameraSettings,
+  path: &str,
+) -> io::Result<()> {
+  let width = args.width;
+  let height = args.height;
+  let camera = camera.build(width as f32 / height as f32);
+  let integrator = args.integrator.build();
 
-    *pixel = image::Rgb([r as u8, g as u8, b as u8]);
+  let start = Instant::now();
+  let sampling = Sampling {
+    kind: args.sampler,
+    seed: args.seed,
+  };
+  let mut accumulator = args.accumulator(width, height, scene.space);
+  scene.render(
+    camera,
+    &*integrator,
+    sampling,
+    args.budget(),
+    &mut accumulator,
+  );
+  let delta = Instant::now() - start;
+  let seconds = delta.as_secs() as f64 + (delta.subsec_millis() as f64 / 1000.0);
+  let average = accumulator.samples_taken() as f64 / (width * height) as f64;
+  println!(
+    "Render completed! {}x{} - {}SPP - {:.2}s",
+    width,
+    height,
+    average.round(),
+    seconds
+  );
+  if args.noise_threshold.is_some() {
+    println!(
+      "Adaptive sampling took {:.1} samples per pixel on average",
+      average
+    );
+  }
+  if !args.budget().is_fixed() {
+    // Pixels need two samples before their variance, and so the noise, is
+    // known.
+    let noise = if accumulator.passes >= 2 {
+      Some(accumulator.image_noise())
+    } else {
+      None
+    };
+    match noise {
+      Some(noise) => println!(
+        "Stopped after {} passes with noise {:.4}",
+        accumulator.passes, noise
+      ),
+      None => println!(
+        "Stopped after {} passes, too few to estimate the noise",
+        accumulator.passes
+      ),
+    }
+    let missed = noise.is_none_or(|noise| args.budget().missed_target(noise));
+    if let (Some(target), true) = (args.target_noise, missed) {
+      println!("Gave up before reaching the target noise of {}", target);
+    }
   }
 
-  imgbuf.save(path).unwrap();
+  let (film, aovs) = finish(&accumulator, &args.aovs, args.denoise);
+  save_render(&film, &aovs, path, args.tone_mapping(), args.exr_pixel_type)
 }
 
 fn main() {
-  let args = Args::from_args();
+  let matches = Args::clap().get_matches();
+  let mut args = Args::from_clap(&matches);
+  if args.list_presets {
+    for preset in PRESETS.iter() {
+      println!("{:<14}{}", preset.name, preset.description);
+    }
+    return;
+  }
+
+  let (mut scene, mut camera) = match args.scene.clone() {
+    Some(path) => match load_scene_file(&path) {
+      Ok((file, scene)) => {
+        args.apply_scene_settings(&file.render, &matches);
+        (scene, file.camera)
+      },
+      Err(e) => {
+        eprintln!("error: failed to load scene: {}", e);
+        process::exit(1);
+      },
+    },
+    None => {
+      let preset = args.preset.unwrap_or(&PRESETS[0]);
+      let (mut scene, camera, render) = preset.build();
+      let spheres = args.random_spheres(&matches).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        process::exit(2);
+      });
+      if let Some(spheres) = spheres {
+        if preset.name != PRESETS[0].name {
+          eprintln!("error: the --sphere options only apply to the random preset");
+          process::exit(2);
+        }
+        scene = Scene::new(spheres.generate());
+      }
+      args.apply_scene_settings(&render, &matches);
+      (scene, camera)
+    },
+  };
+  if let Some(background) = args.background {
+    scene.background = background;
+  }
+
+  for path in &args.obj {
+    match load_obj(path) {
+      Ok(items) => scene.extend(items),
+      Err(e) => {
+        eprintln!("error: failed to load model: {}", e);
+        process::exit(1);
+      },
+    }
+  }
+
+  args.apply_camera_options(&mut camera);
+  if let Err(e) = camera.validate() {
+    eprintln!("error: {}", e);
+    process::exit(2);
+  }
+  match camera.autofocus(&scene, args.width, args.height) {
+    Ok(true) => (),
+    Ok(false) => eprintln!(
+      "warning: autofocus found no surface, keeping the focus distance of {}",
+      camera.focus_distance
+    ),
+    Err(e) => {
+      eprintln!("error: {}", e);
+      process::exit(2);
+    },
+  }
+
+  if let Some(ref path) = args.save_scene {
+    let render = RenderSettings {
+      width: Some(args.width),
+      height: Some(args.height),
+      samples: Some(args.samples),
+      seed: Some(args.seed),
+    };
+    if let Err(e) = save_scene_file(&SceneFile::from_scene(&scene, camera, render), path) {
+      eprintln!("error: failed to save scene: {}", e);
+      process::exit(1);
+    }
+    println!("Saved scene to {}", path.display());
+    return;
+  }
+
+  scene.convert_to(args.working_space);
+  println!("Camera: {}", camera);
+
+  if args.convergence {
+    print_convergence(
+      &scene,
+      camera.build(args.width as f32 / args.height as f32),
+      &*args.integrator.build(),
+      args.width,
+      args.height,
+      args.samples,
+      args.seed,
+    );
+  } else if args.headless || !cfg!(feature = "window") {
+    let path = match args.output {
+      Some(ref path) => path.clone(),
+      None => {
+        eprintln!("error: an --output path is required when rendering without a window");
+        process::exit(2);
+      },
+    };
+
+    if let Err(e) = render_headless(&args, &scene, &camera, &path) {
+      eprintln!("error: failed to write {}: {}", path, e);
+      process::exit(1);
+    }
+  } else {
+    #[cfg(feature = "window")]
+    run_window(&args, &scene, camera);
+  }
+}
+
+#[cfg(feature = "window")]
+fn run_window(args: &Args, scene: &Scene, settings: CameraSettings) {
   let width = args.width;
   let height = args.height;
   let samples = args.samples;
+  let budget = args.budget();
+  let integrator = args.integrator;
+  let sampling = Sampling {
+    kind: args.sampler,
+    seed: args.seed,
+  };
 
-  let mut buffer: Option<Vec<u32>> = None;
+  let wanted = args.aovs.clone();
+  let render_aovs = args.render_aovs();
+  let denoised = args.denoise;
+  let filter = args.filter;
+  let noise_threshold = args.noise_threshold;
+  // The finished image and its AOVs, kept for saving.
+  let mut buffer: Option<(Film, Vec<(Aov, Film)>)> = None;
   let mut window = Window::new(
     "PathTracer",
     width,
@@ -82,7 +587,8 @@ fn main() {
       scale: SCALE,
       ..Default::default()
     },
-  ).unwrap_or_else(|e| {
+  )
+  .unwrap_or_else(|e| {
     panic!("{}", e);
   });
 
@@ -99,13 +605,6 @@ fn main() {
 
   window.add_menu(&file_menu);
 
-  let eye = Vector3::new(13.0, 2.0, 3.0);
-  let look_at = Vector3::new(0.0, 0.0, 0.0);
-  let focus_dist = 10.0;
-  let aperture = 0.1;
-
-  let scene = Scene::random();
-
   let scale_factor: usize = match SCALE {
     Scale::X1 => 1,
     Scale::X2 => 2,
@@ -117,30 +616,44 @@ fn main() {
   };
 
   let start = Instant::now();
-  let mut current_render_job: Option<(Receiver<Vec<u32>>, thread::JoinHandle<_>)> = None;
+  let mut current_render_job: Option<RenderJob> = None;
+  let mut passes = 0;
+  let mut seconds = 0.0;
+  let mut tone_mapping = args.tone_mapping();
+  let exr_pixel_type = args.exr_pixel_type;
+  // The latest pass, kept to redisplay when tone mapping changes.
+  let mut shown: Option<Film> = None;
   let mut running = true;
   while running {
     let (w, h) = window.get_size();
     let w = w / scale_factor;
     let h = h / scale_factor;
-//    let pixels = w * h;
+    //    let pixels = w * h;
 
     running = window.is_open();
     window.is_menu_pressed().map(|menu_id| {
       match menu_id {
         FILE_SAVE => {
-          if let Some(img) = buffer.take() {
-            let result = nfd::open_save_dialog(Some("png"), None).unwrap_or_else(|e| {
+          if let Some((img, img_aovs)) = buffer.take() {
+            let result = nfd::open_save_dialog(Some("png,exr,hdr,pfm"), None).unwrap_or_else(|e| {
               panic!(e);
             });
 
-            match result {
-              Response::Okay(path) => save_buffer_to_path(w as u32, h as u32, &img, &path),
-              Response::OkayMultiple(paths) => save_buffer_to_path(w as u32, h as u32, &img, &paths[0]),
-              Response::Cancel => (),
+            let path = match result {
+              Response::Okay(path) => Some(path),
+              Response::OkayMultiple(paths) => paths.into_iter().next(),
+              Response::Cancel => None,
+            };
+            let saved = match path {
+              Some(path) => save_render(&img, &img_aovs, &path, tone_mapping, exr_pixel_type),
+              None => Ok(()),
+            };
+
+            if let Err(e) = saved {
+              eprintln!("error: failed to save image: {}", e);
             }
 
-            buffer = Some(img);
+            buffer = Some((img, img_aovs));
           }
         },
         FILE_QUIT => running = false,
@@ -148,43 +661,101 @@ fn main() {
       };
     });
 
+    let mut retone = false;
+    if window.is_key_pressed(Key::T, KeyRepeat::No) {
+      tone_mapping.next_operator();
+      retone = true;
+    }
+    if window.is_key_pressed(Key::Up, KeyRepeat::Yes) {
+      tone_mapping.exposure += 0.5;
+      retone = true;
+    }
+    if window.is_key_pressed(Key::Down, KeyRepeat::Yes) {
+      tone_mapping.exposure -= 0.5;
+      retone = true;
+    }
+    if retone {
+      if let Some(ref img) = shown {
+        window
+          .update_with_buffer(&img.to_rgb24(tone_mapping))
+          .unwrap();
+      }
+    }
+
     if current_render_job.is_none() && buffer.is_none() {
       window.set_title(&format!("PathTracer - {}x{}", w, h));
 
-      let camera = Camera::new(
-        eye,
-        look_at,
-        Vector3::up(),
-        20.0,
-        w as f32 / h as f32,
-        aperture,
-        focus_dist,
-      );
+      let camera = settings.build(w as f32 / h as f32);
 
       let scene_copy = scene.clone();
+      let wanted = wanted.clone();
+      let render_aovs = render_aovs.clone();
       let (tx, rx) = channel();
       let handle = thread::spawn(move || {
-        let buffer = scene_copy.render(camera, w, h, samples);
-        tx.send(buffer).unwrap();
-        println!("Render completed!");
+        // Send every pass so the window fills in while samples accumulate.
+        let integrator = integrator.build();
+        let mut accumulator =
+          Accumulator::with_options(w, h, scene_copy.space, filter, &render_aovs);
+        accumulator.noise_threshold = noise_threshold;
+        let start = Instant::now();
+        while accumulator.continues(&budget, start.elapsed()) {
+          scene_copy.render_pass(camera, &*integrator, sampling, samples, &mut accumulator);
+          if tx
+            .send((accumulator.passes, false, accumulator.film(), vec![]))
+            .is_err()
+          {
+            return;
+          }
+        }
+
+        let (film, aovs) = finish(&accumulator, &wanted, denoised);
+        if tx.send((accumulator.passes, true, film, aovs)).is_ok() {
+          println!("Render completed!");
+        }
       });
 
       current_render_job = Some((rx, handle));
     }
 
     if let Some((rx, handle)) = current_render_job.take() {
-      if let Ok(img) = rx.try_recv() {
-        window.update_with_buffer(&img).unwrap();
-        buffer = Some(img);
-      } else {
+      // Only the latest pass matters if several arrived since the last frame.
+      let latest = rx.try_iter().last();
+      let finished = match latest {
+        Some((done, finished, img, img_aovs)) => {
+          window
+            .update_with_buffer(&img.to_rgb24(tone_mapping))
+            .unwrap();
+          passes = done;
+
+          if finished {
+            if let Some(ref path) = args.output {
+              let saved = save_render(&img, &img_aovs, path, tone_mapping, exr_pixel_type);
+              if let Err(e) = saved {
+                eprintln!("error: failed to write {}: {}", path, e);
+              }
+            }
+
+            buffer = Some((img.clone(), img_aovs));
+          }
+
+          shown = Some(img);
+          finished
+        },
+        None => false,
+      };
+
+      if !finished {
         current_render_job = Some((rx, handle));
       }
 
       let delta = Instant::now() - start;
-      let seconds = delta.as_secs() as f64 + (delta.subsec_millis() as f64 / 1000.0);
+      seconds = delta.as_secs() as f64 + (delta.subsec_millis() as f64 / 1000.0);
+    }
+
+    if shown.is_some() {
       window.set_title(&format!(
-        "PathTracer - {}x{} - {}SPP - {:.2}s",
-        w, h, samples, seconds
+        "PathTracer - {}x{} - {}/{}SPP - {:.2}s - {} {:+.1}EV",
+        w, h, passes, samples, seconds, tone_mapping.operator, tone_mapping.exposure
       ));
     }
 
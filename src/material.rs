@@ -1,36 +1,59 @@
-use super::math::*;
+use super::{math::*, Sampler};
 
-use std::sync::Mutex;
-
-use rand::{distributions::Uniform, Rng, SeedableRng, XorShiftRng};
-
-const SEED: [u8; 16] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16];
-
-lazy_static! {
-  static ref MATERIAL_RNG: Mutex<XorShiftRng> = Mutex::new(XorShiftRng::from_seed(SEED));
-}
+use std::f32;
 
 #[derive(Debug, Clone, Copy)]
 pub struct Bounce {
   pub attenuation: Vector3,
   pub bounced: Ray,
+  /// Solid angle density with which `bounced` was chosen, or `None` for
+  /// specular bounces that light sampling could never reproduce.
+  pub pdf: Option<f32>,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Material {
   Lambertian { albedo: Vector3 },
   Metallic { albedo: Vector3, roughness: f32 },
   Dielectric { refractive_index: f32 },
+  Emissive { emission: Vector3 },
+}
+
+/// Flips `normal` to the side of the surface that `r` arrived from.
+pub fn facing(r: Ray, normal: Vector3) -> Vector3 {
+  if r.direction.dot(normal) > 0.0 {
+    -normal
+  } else {
+    normal
+  }
+}
+
+fn lambertian_pdf(normal: Vector3, direction: Vector3) -> f32 {
+  direction.as_unit().dot(normal).max(0.0) / f32::consts::PI
 }
 
-fn lambertian_scatter(_: Ray, point: Vector3, normal: Vector3, albedo: Vector3) -> Option<Bounce> {
-  let target = point + normal + Vector3::random_unit_sphere();
-  let bounced = Ray::new(point, target - point);
+fn lambertian_scatter(
+  r: Ray,
+  point: Vector3,
+  normal: Vector3,
+  albedo: Vector3,
+  sampler: &mut dyn Sampler,
+) -> Option<Bounce> {
+  // Offsetting a point on the unit sphere by the normal gives a cosine
+  // weighted direction, so the albedo alone is the attenuation.
+  let normal = facing(r, normal);
+  let mut direction = normal + Vector3::random_unit_vector(sampler);
+  if direction.length_squared() < 1e-8 {
+    direction = normal;
+  }
+
+  let bounced = Ray::new(point, direction);
   let attenuation = albedo;
-  return Some(Bounce {
+  Some(Bounce {
     attenuation,
     bounced,
-  });
+    pdf: Some(lambertian_pdf(normal, direction)),
+  })
 }
 
 fn metallic_scatter(
@@ -39,14 +62,19 @@ fn metallic_scatter(
   normal: Vector3,
   albedo: Vector3,
   roughness: f32,
+  sampler: &mut dyn Sampler,
 ) -> Option<Bounce> {
   let target = r.direction.as_unit().reflect(normal);
-  let bounced = Ray::new(point, target + roughness * Vector3::random_unit_sphere());
+  let bounced = Ray::new(
+    point,
+    target + roughness * Vector3::random_unit_sphere(sampler),
+  );
   let attenuation = albedo;
   if target.dot(normal) > 0.0 {
     Some(Bounce {
       attenuation,
       bounced,
+      pdf: None,
     })
   } else {
     None
@@ -64,6 +92,7 @@ fn dielectric_scatter(
   point: Vector3,
   normal: Vector3,
   refractive_index: f32,
+  sampler: &mut dyn Sampler,
 ) -> Option<Bounce> {
   let reflection = r.direction.reflect(normal);
   let attenuation = Vector3::new(1.0, 1.0, 1.0);
@@ -82,9 +111,7 @@ fn dielectric_scatter(
       (Vector3::zero(), 1.0)
     };
 
-  let uniform = Uniform::new(0.0f32, 1.0f32);
-  let mut rng = MATERIAL_RNG.lock().unwrap();
-  let bounced = if rng.sample(uniform) < reflect_prob {
+  let bounced = if sampler.next_1d() < reflect_prob {
     Ray::new(point, reflection)
   } else {
     Ray::new(point, refraction)
@@ -93,6 +120,7 @@ fn dielectric_scatter(
   Some(Bounce {
     attenuation,
     bounced,
+    pdf: None,
   })
 }
 
@@ -110,15 +138,78 @@ impl Material {
     Material::Dielectric { refractive_index }
   }
 
-  pub fn scatter(&self, r: Ray, point: Vector3, normal: Vector3) -> Option<Bounce> {
+  pub fn emissive(emission: Vector3) -> Material {
+    Material::Emissive { emission }
+  }
+
+  /// Radiance given off by the surface itself, independent of any light
+  /// arriving at it.
+  pub fn emitted(&self) -> Vector3 {
     match self {
-      Material::Lambertian { albedo } => lambertian_scatter(r, point, normal, *albedo),
+      Material::Emissive { emission } => *emission,
+      _ => Vector3::zero(),
+    }
+  }
+
+  pub fn scatter(
+    &self,
+    r: Ray,
+    point: Vector3,
+    normal: Vector3,
+    sampler: &mut dyn Sampler,
+  ) -> Option<Bounce> {
+    match self {
+      Material::Lambertian { albedo } => lambertian_scatter(r, point, normal, *albedo, sampler),
       Material::Metallic { albedo, roughness } => {
-        metallic_scatter(r, point, normal, *albedo, *roughness)
+        metallic_scatter(r, point, normal, *albedo, *roughness, sampler)
       },
       Material::Dielectric { refractive_index } => {
-        dielectric_scatter(r, point, normal, *refractive_index)
+        dielectric_scatter(r, point, normal, *refractive_index, sampler)
       },
+      Material::Emissive { .. } => None,
+    }
+  }
+
+  /// The same material with its colours transformed by `m`, such as a
+  /// conversion into another colour space.
+  pub fn transform_colors(self, m: Matrix3) -> Material {
+    match self {
+      Material::Lambertian { albedo } => Material::Lambertian { albedo: m * albedo },
+      Material::Metallic { albedo, roughness } => Material::Metallic {
+        albedo: m * albedo,
+        roughness,
+      },
+      Material::Dielectric { .. } => self,
+      Material::Emissive { emission } => Material::Emissive {
+        emission: m * emission,
+      },
+    }
+  }
+
+  /// Overall surface colour, ignoring directional effects.
+  pub fn albedo(&self) -> Vector3 {
+    match self {
+      Material::Lambertian { albedo } | Material::Metallic { albedo, .. } => *albedo,
+      Material::Dielectric { .. } => Vector3::one(),
+      Material::Emissive { emission } => emission.min(Vector3::one()),
+    }
+  }
+
+  /// Reflected fraction of light arriving along `direction`, including the
+  /// cosine term, for a surface hit by `r`. Zero for specular materials.
+  pub fn eval(&self, r: Ray, normal: Vector3, direction: Vector3) -> Vector3 {
+    match self {
+      Material::Lambertian { albedo } => *albedo * lambertian_pdf(facing(r, normal), direction),
+      _ => Vector3::zero(),
+    }
+  }
+
+  /// Solid angle density with which `scatter` chooses `direction` for a
+  /// surface hit by `r`. Zero for specular materials.
+  pub fn pdf(&self, r: Ray, normal: Vector3, direction: Vector3) -> f32 {
+    match self {
+      Material::Lambertian { .. } => lambertian_pdf(facing(r, normal), direction),
+      _ => 0.0,
     }
   }
 }
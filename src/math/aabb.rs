@@ -0,0 +1,87 @@
+use super::*;
+use std::f32;
+
+pub trait Bounded {
+  fn bounds(&self) -> Aabb;
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+  pub min: Vector3,
+  pub max: Vector3,
+}
+
+impl Aabb {
+  pub fn new(min: Vector3, max: Vector3) -> Aabb {
+    Aabb { min, max }
+  }
+
+  /// An inverted box that any union or grow will replace outright.
+  pub fn empty() -> Aabb {
+    Aabb {
+      min: Vector3::new(f32::MAX, f32::MAX, f32::MAX),
+      max: Vector3::new(f32::MIN, f32::MIN, f32::MIN),
+    }
+  }
+
+  pub fn union(self, rhs: Aabb) -> Aabb {
+    Aabb::new(self.min.min(rhs.min), self.max.max(rhs.max))
+  }
+
+  pub fn grow(self, point: Vector3) -> Aabb {
+    Aabb::new(self.min.min(point), self.max.max(point))
+  }
+
+  pub fn extent(self) -> Vector3 {
+    self.max - self.min
+  }
+
+  pub fn centroid(self) -> Vector3 {
+    0.5 * (self.min + self.max)
+  }
+
+  pub fn largest_axis(self) -> usize {
+    let e = self.extent();
+    if e.x() > e.y() && e.x() > e.z() {
+      0
+    } else if e.y() > e.z() {
+      1
+    } else {
+      2
+    }
+  }
+
+  pub fn surface_area(self) -> f32 {
+    let e = self.extent();
+    if e.x() < 0.0 || e.y() < 0.0 || e.z() < 0.0 {
+      return 0.0;
+    }
+
+    2.0 * (e.x() * e.y() + e.y() * e.z() + e.z() * e.x())
+  }
+}
+
+impl Collidable<Ray> for Aabb {
+  /// Distance along the ray at which it enters the box.
+  type Output = Option<f32>;
+
+  fn hit(&self, r: Ray) -> Option<f32> {
+    let mut t_min = 0.0f32;
+    let mut t_max = f32::MAX;
+
+    for axis in 0..3 {
+      let inv_d = 1.0 / r.direction[axis];
+      let t0 = (self.min[axis] - r.origin[axis]) * inv_d;
+      let t1 = (self.max[axis] - r.origin[axis]) * inv_d;
+      let (t0, t1) = if inv_d < 0.0 { (t1, t0) } else { (t0, t1) };
+
+      t_min = t_min.max(t0);
+      t_max = t_max.min(t1);
+      if t_max < t_min {
+        return None;
+      }
+    }
+
+    Some(t_min)
+  }
+}
@@ -0,0 +1,42 @@
+use super::Vector3;
+
+use std::ops;
+
+/// Row-major 3x3 matrix, used for linear colour space conversions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Matrix3 {
+  rows: [[f32; 3]; 3],
+}
+
+impl Matrix3 {
+  pub fn new(rows: [[f32; 3]; 3]) -> Matrix3 {
+    Matrix3 { rows }
+  }
+
+  pub fn identity() -> Matrix3 {
+    Matrix3::new([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]])
+  }
+}
+
+impl ops::Mul<Vector3> for Matrix3 {
+  type Output = Vector3;
+
+  fn mul(self, v: Vector3) -> Vector3 {
+    let row = |r: [f32; 3]| r[0] * v[0] + r[1] * v[1] + r[2] * v[2];
+    Vector3::new(row(self.rows[0]), row(self.rows[1]), row(self.rows[2]))
+  }
+}
+
+impl ops::Mul for Matrix3 {
+  type Output = Matrix3;
+
+  fn mul(self, rhs: Matrix3) -> Matrix3 {
+    let mut rows = [[0.0; 3]; 3];
+    for (i, row) in rows.iter_mut().enumerate() {
+      for (j, value) in row.iter_mut().enumerate() {
+        *value = (0..3).map(|k| self.rows[i][k] * rhs.rows[k][j]).sum();
+      }
+    }
+    Matrix3::new(rows)
+  }
+}
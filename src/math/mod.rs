@@ -1,5 +1,8 @@
+mod aabb;
+mod matrix3;
 mod ray;
 mod sphere;
+mod triangle;
 mod vector3;
 
-pub use self::{ray::*, sphere::*, vector3::*};
+pub use self::{aabb::*, matrix3::*, ray::*, sphere::*, triangle::*, vector3::*};
@@ -21,6 +21,12 @@ pub struct RayHit {
   pub t: f32,
   pub point: Vector3,
   pub normal: Vector3,
+  /// Surface coordinates of the hit. Triangles report the barycentric
+  /// weights of their second and third vertices, meshes their interpolated
+  /// texture coordinates if they have any, and spheres their latitude and
+  /// longitude mapped to [0, 1].
+  pub u: f32,
+  pub v: f32,
 }
 
 pub trait Collidable<TPrimitive> {
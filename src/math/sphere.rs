@@ -11,6 +11,75 @@ impl Sphere {
   pub fn new(center: Vector3, radius: f32) -> Sphere {
     Sphere { center, radius }
   }
+
+  pub fn area(&self) -> f32 {
+    4.0 * f32::consts::PI * self.radius * self.radius
+  }
+
+  /// Maps uniform `u` and `v` to a direction from `origin` towards the
+  /// sphere, distributed uniformly over the cone of directions it subtends.
+  pub fn sample_direction(&self, origin: Vector3, u: f32, v: f32) -> Vector3 {
+    let phi = 2.0 * f32::consts::PI * v;
+    let to_center = self.center - origin;
+    let distance_squared = to_center.length_squared();
+    let radius_squared = self.radius * self.radius;
+    if distance_squared <= radius_squared {
+      // From inside, every direction reaches the sphere.
+      let z = 1.0 - 2.0 * u;
+      let r = (1.0 - z * z).max(0.0).sqrt();
+      return Vector3::new(r * phi.cos(), r * phi.sin(), z);
+    }
+
+    let cos_theta = 1.0 - u * self.one_minus_cos_max(distance_squared);
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let w = to_center / distance_squared.sqrt();
+    let (x, y) = w.basis();
+    sin_theta * phi.cos() * x + sin_theta * phi.sin() * y + cos_theta * w
+  }
+
+  /// Solid angle density, as seen from `origin`, of choosing `direction`
+  /// with `sample_direction`.
+  pub fn pdf(&self, origin: Vector3, direction: Vector3) -> f32 {
+    if self.hit(Ray::new(origin, direction)).is_none() {
+      return 0.0;
+    }
+
+    let distance_squared = (self.center - origin).length_squared();
+    if distance_squared <= self.radius * self.radius {
+      1.0 / (4.0 * f32::consts::PI)
+    } else {
+      1.0 / (2.0 * f32::consts::PI * self.one_minus_cos_max(distance_squared))
+    }
+  }
+
+  /// One minus the cosine of the half angle subtended by the sphere, written
+  /// to stay accurate for small and distant spheres.
+  fn one_minus_cos_max(&self, distance_squared: f32) -> f32 {
+    let sin2_max = self.radius * self.radius / distance_squared;
+    let cos_max = (1.0 - sin2_max).max(0.0).sqrt();
+    sin2_max / (1.0 + cos_max)
+  }
+
+  fn hit_at(&self, r: Ray, t: f32) -> RayHit {
+    let point = r.point_at_parameter(t);
+    let normal = (point - self.center) / self.radius;
+    let phi = normal.z().atan2(normal.x());
+    let theta = normal.y().clamp(-1.0, 1.0).asin();
+    RayHit {
+      t,
+      point,
+      normal,
+      u: 1.0 - (phi + f32::consts::PI) / (2.0 * f32::consts::PI),
+      v: (theta + f32::consts::FRAC_PI_2) / f32::consts::PI,
+    }
+  }
+}
+
+impl Bounded for Sphere {
+  fn bounds(&self) -> Aabb {
+    let r = Vector3::new(self.radius, self.radius, self.radius);
+    Aabb::new(self.center - r, self.center + r)
+  }
 }
 
 impl Collidable<Ray> for Sphere {
@@ -30,15 +99,11 @@ impl Collidable<Ray> for Sphere {
     let d = (b * b - (a * c)).sqrt();
     let t = (-b - d) / a;
     if t < f32::MAX && t > 0.001 {
-      let point = r.point_at_parameter(t);
-      let normal = (point - self.center) / self.radius;
-      Some(RayHit { t, point, normal })
+      Some(self.hit_at(r, t))
     } else {
-      let t = (-b - d) / a;
+      let t = (-b + d) / a;
       if t < f32::MAX && t > 0.001 {
-        let point = r.point_at_parameter(t);
-        let normal = (point - self.center) / self.radius;
-        Some(RayHit { t, point, normal })
+        Some(self.hit_at(r, t))
       } else {
         None
       }
@@ -0,0 +1,118 @@
+use super::*;
+use std::f32;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Triangle {
+  pub a: Vector3,
+  pub b: Vector3,
+  pub c: Vector3,
+}
+
+impl Triangle {
+  pub fn new(a: Vector3, b: Vector3, c: Vector3) -> Triangle {
+    Triangle { a, b, c }
+  }
+
+  pub fn area(&self) -> f32 {
+    0.5 * (self.b - self.a).cross(self.c - self.a).length()
+  }
+
+  /// Maps uniform `u` and `v` to a point distributed uniformly over the
+  /// triangle.
+  pub fn sample(&self, u: f32, v: f32) -> Vector3 {
+    let su = u.sqrt();
+    let b0 = 1.0 - su;
+    let b1 = v * su;
+    b0 * self.a + b1 * self.b + (1.0 - b0 - b1) * self.c
+  }
+
+  /// Solid angle density, as seen from `origin`, of choosing the point along
+  /// `direction` with `sample`.
+  pub fn pdf(&self, origin: Vector3, direction: Vector3) -> f32 {
+    self
+      .hit(Ray::new(origin, direction))
+      .map_or(0.0, |hit| solid_angle_pdf(1.0 / self.area(), origin, &hit))
+  }
+}
+
+/// Converts a density over surface area at `hit` into a density over solid
+/// angle as seen from `origin`.
+pub fn solid_angle_pdf(area_pdf: f32, origin: Vector3, hit: &RayHit) -> f32 {
+  let to_point = hit.point - origin;
+  let cosine = to_point.as_unit().dot(hit.normal).abs();
+  if cosine > 0.0 {
+    area_pdf * to_point.length_squared() / cosine
+  } else {
+    0.0
+  }
+}
+
+impl Bounded for Triangle {
+  fn bounds(&self) -> Aabb {
+    Aabb::empty().grow(self.a).grow(self.b).grow(self.c)
+  }
+}
+
+impl Collidable<Ray> for Triangle {
+  type Output = Option<RayHit>;
+
+  /// Möller-Trumbore intersection. The reported `u` and `v` are the
+  /// barycentric weights of `b` and `c` respectively.
+  fn hit(&self, r: Ray) -> Option<RayHit> {
+    let edge1 = self.b - self.a;
+    let edge2 = self.c - self.a;
+    let p = r.direction.cross(edge2);
+    let det = edge1.dot(p);
+    // `det` scales with the lengths of the edges and the direction, so the
+    // test for a ray parallel to the triangle must scale with them too.
+    if det.abs() <= f32::EPSILON * edge1.length() * edge2.length() * r.direction.length() {
+      return None;
+    }
+
+    let inv_det = 1.0 / det;
+    let s = r.origin - self.a;
+    let u = s.dot(p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+      return None;
+    }
+
+    let q = s.cross(edge1);
+    let v = r.direction.dot(q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+      return None;
+    }
+
+    let t = edge2.dot(q) * inv_det;
+    if t < f32::MAX && t > 0.001 {
+      Some(RayHit {
+        t,
+        point: r.point_at_parameter(t),
+        normal: edge1.cross(edge2).as_unit(),
+        u,
+        v,
+      })
+    } else {
+      None
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn hits_small_triangles() {
+    let size = 1e-4;
+    let triangle = Triangle::new(
+      Vector3::new(0.0, 0.0, -1.0),
+      Vector3::new(size, 0.0, -1.0),
+      Vector3::new(0.0, size, -1.0),
+    );
+    let r = Ray::new(
+      Vector3::zero(),
+      Vector3::new(0.25 * size, 0.25 * size, -1.0),
+    );
+    assert!(triangle.hit(r).is_some());
+  }
+}
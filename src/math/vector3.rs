@@ -1,16 +1,12 @@
 #![allow(dead_code)]
 
-use std::{fmt, ops, sync::Mutex};
+use std::{f32, fmt, ops, str::FromStr};
 
-use rand::{distributions::Uniform, Rng, SeedableRng, XorShiftRng};
+use sampler::Sampler;
 
-const SEED: [u8; 16] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16];
-
-lazy_static! {
-  static ref RNG: Mutex<XorShiftRng> = Mutex::new(XorShiftRng::from_seed(SEED));
-}
-
-#[derive(Debug, Clone, Copy)]
+/// Written in scene files as an `(x, y, z)` tuple.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(from = "[f32; 3]", into = "[f32; 3]")]
 pub struct Vector3 {
   e: [f32; 3],
 }
@@ -40,40 +36,54 @@ impl Vector3 {
     Vector3::new(0.0, 0.0, 0.0)
   }
 
-  pub fn random_unit_disk() -> Vector3 {
-    let uniform = Uniform::new(0.0f32, 1.0f32);
-    let mut result = None;
-    let one = Vector3::new(1.0, 1.0, 0.0);
-    let mut rng = RNG.lock().unwrap();
-    while result.is_none() {
-      let r = Vector3::new(rng.sample(uniform), rng.sample(uniform), 0.0);
-      let p = 2.0 * r - one;
-      if p.dot(p) < 1.0 {
-        result = Some(p)
-      }
-    }
-
-    result.unwrap()
-  }
-
-  pub fn random_unit_sphere() -> Vector3 {
-    let uniform = Uniform::new(0.0f32, 1.0f32);
-    let mut result = None;
-    let one = Vector3::one();
-    let mut rng = RNG.lock().unwrap();
-    while result.is_none() {
-      let r = Vector3::new(
-        rng.sample(uniform),
-        rng.sample(uniform),
-        rng.sample(uniform),
-      );
-      let p = 2.0 * r - one;
-      if p.length_squared() < 1.0 {
-        result = Some(p)
-      }
+  /// Random point uniformly distributed over the unit disk in the xy plane.
+  pub fn random_unit_disk(sampler: &mut dyn Sampler) -> Vector3 {
+    // Concentric mapping of the unit square, which uses exactly two samples
+    // and keeps nearby samples nearby on the disk.
+    let (u, v) = sampler.next_2d();
+    let (a, b) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+    if a == 0.0 && b == 0.0 {
+      return Vector3::zero();
     }
 
-    result.unwrap()
+    let quarter = f32::consts::FRAC_PI_4;
+    let (r, theta) = if a.abs() > b.abs() {
+      (a, quarter * (b / a))
+    } else {
+      (b, 2.0 * quarter - quarter * (a / b))
+    };
+    Vector3::new(r * theta.cos(), r * theta.sin(), 0.0)
+  }
+
+  /// Random point uniformly distributed within the unit ball.
+  pub fn random_unit_sphere(sampler: &mut dyn Sampler) -> Vector3 {
+    let direction = Vector3::random_unit_vector(sampler);
+    direction * sampler.next_1d().cbrt()
+  }
+
+  /// Random direction uniformly distributed over the unit sphere.
+  pub fn random_unit_vector(sampler: &mut dyn Sampler) -> Vector3 {
+    let (u, v) = sampler.next_2d();
+    let z = 1.0 - 2.0 * u;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * f32::consts::PI * v;
+    Vector3::new(r * phi.cos(), r * phi.sin(), z)
+  }
+
+  /// Two unit vectors which together with `self` (assumed normalized) form an
+  /// orthonormal basis.
+  pub fn basis(self) -> (Vector3, Vector3) {
+    let sign = 1.0f32.copysign(self.z());
+    let a = -1.0 / (sign + self.z());
+    let b = self.x() * self.y() * a;
+    (
+      Vector3::new(
+        1.0 + sign * self.x() * self.x() * a,
+        sign * b,
+        -sign * self.x(),
+      ),
+      Vector3::new(b, sign + self.y() * self.y() * a, -self.y()),
+    )
   }
 
   pub fn x(&self) -> f32 {
@@ -147,18 +157,54 @@ impl Vector3 {
     }
   }
 
+  pub fn min(self, rhs: Vector3) -> Vector3 {
+    Vector3::new(
+      self.e[0].min(rhs.e[0]),
+      self.e[1].min(rhs.e[1]),
+      self.e[2].min(rhs.e[2]),
+    )
+  }
+
+  pub fn max(self, rhs: Vector3) -> Vector3 {
+    Vector3::new(
+      self.e[0].max(rhs.e[0]),
+      self.e[1].max(rhs.e[1]),
+      self.e[2].max(rhs.e[2]),
+    )
+  }
+
   pub fn lerp(t: f32, lhs: Vector3, rhs: Vector3) -> Vector3 {
     (1.0 - t) * lhs + t * rhs
   }
 
+  /// Packs the channels, clamped to [0, 1], as 0RGB bytes.
   pub fn to_rgb24(self) -> u32 {
-    let r = (self.r() * 255.99f32).trunc() as u32;
-    let g = (self.g() * 255.99f32).trunc() as u32;
-    let b = (self.b() * 255.99f32).trunc() as u32;
+    let byte = |c: f32| (c.clamp(0.0, 1.0) * 255.99f32).trunc() as u32;
+    let (r, g, b) = (byte(self.r()), byte(self.g()), byte(self.b()));
     (r << 16) | (g << 8) | b
   }
 }
 
+impl From<[f32; 3]> for Vector3 {
+  fn from(e: [f32; 3]) -> Vector3 {
+    Vector3 { e }
+  }
+}
+
+impl From<Vector3> for [f32; 3] {
+  fn from(v: Vector3) -> [f32; 3] {
+    v.e
+  }
+}
+
+impl ops::Index<usize> for Vector3 {
+  type Output = f32;
+
+  fn index(&self, index: usize) -> &f32 {
+    &self.e[index]
+  }
+}
+
 impl ops::Neg for Vector3 {
   type Output = Vector3;
 
@@ -304,3 +350,22 @@ impl fmt::Display for Vector3 {
     write!(f, "({}, {}, {})", self.x(), self.y(), self.z())
   }
 }
+
+impl FromStr for Vector3 {
+  type Err = String;
+
+  /// Parses three comma separated components, e.g. `0.5,0.7,1`.
+  fn from_str(s: &str) -> Result<Vector3, String> {
+    let components = s
+      .split(',')
+      .map(|c| c.trim().parse::<f32>())
+      .collect::<Result<Vec<f32>, _>>()
+      .map_err(|e| format!("invalid vector '{}': {}", s, e))?;
+
+    if components.len() != 3 {
+      return Err(format!("expected three components in '{}'", s));
+    }
+
+    Ok(Vector3::new(components[0], components[1], components[2]))
+  }
+}
@@ -0,0 +1,210 @@
+use super::{solid_angle_pdf, Aabb, Bounded, Bvh, Collidable, Ray, RayHit, Triangle, Vector3};
+
+/// Indexed triangle mesh. Triangles share vertices through `indices`, three
+/// per triangle, and are found through a BVH local to the mesh so the whole
+/// mesh can be a single scene item.
+#[derive(Debug, Clone)]
+pub struct Mesh {
+  pub positions: Vec<Vector3>,
+  /// Per-vertex shading normals; empty to shade with the face normal.
+  pub normals: Vec<Vector3>,
+  /// Per-vertex texture coordinates, reported as the `u` and `v` of hits;
+  /// may be empty.
+  pub texcoords: Vec<(f32, f32)>,
+  pub indices: Vec<u32>,
+  bvh: Bvh,
+  /// Running total of triangle areas, for sampling triangles by area.
+  area_cdf: Vec<f32>,
+}
+
+impl Mesh {
+  pub fn new(
+    positions: Vec<Vector3>,
+    normals: Vec<Vector3>,
+    texcoords: Vec<(f32, f32)>,
+    indices: Vec<u32>,
+  ) -> Mesh {
+    Mesh::try_new(positions, normals, texcoords, indices)
+      .unwrap_or_else(|error| panic!("{}", error))
+  }
+
+  /// Builds a mesh, or describes what is wrong with the data given. Meshes
+  /// must have some area, so that there is something to hit and, for
+  /// lights, to sample.
+  pub fn try_new(
+    positions: Vec<Vector3>,
+    normals: Vec<Vector3>,
+    texcoords: Vec<(f32, f32)>,
+    indices: Vec<u32>,
+  ) -> Result<Mesh, String> {
+    if indices.is_empty() {
+      return Err("mesh has no triangles".to_string());
+    }
+    if !indices.len().is_multiple_of(3) {
+      return Err(format!(
+        "mesh index count {} is not a multiple of three",
+        indices.len()
+      ));
+    }
+    if let Some(index) = indices.iter().find(|&&i| i as usize >= positions.len()) {
+      return Err(format!(
+        "mesh index {} is out of range ({} positions)",
+        index,
+        positions.len()
+      ));
+    }
+    if !normals.is_empty() && normals.len() != positions.len() {
+      return Err(format!(
+        "mesh has {} normals for {} positions, expected one per vertex or none",
+        normals.len(),
+        positions.len()
+      ));
+    }
+    if !texcoords.is_empty() && texcoords.len() != positions.len() {
+      return Err(format!(
+        "mesh has {} texture coordinates for {} positions, expected one per vertex or none",
+        texcoords.len(),
+        positions.len()
+      ));
+    }
+
+    let mut mesh = Mesh {
+      positions,
+      normals,
+      texcoords,
+      indices,
+      bvh: Bvh::build::<Triangle>(&[]),
+      area_cdf: vec![],
+    };
+    let triangles: Vec<Triangle> = (0..mesh.triangle_count())
+      .map(|index| mesh.triangle(index))
+      .collect();
+    mesh.bvh = Bvh::build(&triangles);
+
+    let mut area = 0.0;
+    mesh.area_cdf = triangles
+      .iter()
+      .map(|triangle| {
+        area += triangle.area();
+        area
+      })
+      .collect();
+    if mesh.area() > 0.0 {
+      Ok(mesh)
+    } else {
+      Err("mesh has no area, as every triangle in it is degenerate".to_string())
+    }
+  }
+
+  pub fn area(&self) -> f32 {
+    self.area_cdf.last().cloned().unwrap_or(0.0)
+  }
+
+  /// Maps uniform `u` and `v` to a direction from `origin` towards a point
+  /// distributed uniformly over the surface of the mesh, returning it along
+  /// with the index of the triangle the point is on.
+  pub fn sample_direction(&self, origin: Vector3, u: f32, v: f32) -> (usize, Vector3) {
+    let target = u * self.area();
+    let index = match self.area_cdf.binary_search_by(|a| a.total_cmp(&target)) {
+      Ok(index) | Err(index) => index.min(self.area_cdf.len() - 1),
+    };
+
+    // Rescale `u` to the span of the chosen triangle so it can be reused.
+    let start = if index > 0 {
+      self.area_cdf[index - 1]
+    } else {
+      0.0
+    };
+    let span = self.area_cdf[index] - start;
+    let u = if span > 0.0 {
+      ((target - start) / span).min(1.0)
+    } else {
+      0.0
+    };
+
+    (index, self.triangle(index).sample(u, v) - origin)
+  }
+
+  /// Solid angle density, as seen from `origin`, of `sample_direction`
+  /// choosing `direction` towards a point on triangle `index`.
+  pub fn triangle_pdf(&self, index: usize, origin: Vector3, direction: Vector3) -> f32 {
+    self
+      .triangle(index)
+      .hit(Ray::new(origin, direction))
+      .map_or(0.0, |hit| solid_angle_pdf(1.0 / self.area(), origin, &hit))
+  }
+
+  /// Solid angle density, as seen from `origin`, of choosing `direction`
+  /// with `sample_direction` and finding the first triangle along it
+  /// unoccluded, which is how a light sample must reach its point.
+  pub fn pdf(&self, origin: Vector3, direction: Vector3) -> f32 {
+    // Intersect the triangles directly so the density uses the geometric
+    // normal rather than an interpolated shading normal.
+    let r = Ray::new(origin, direction);
+    self
+      .bvh
+      .hit_with(r, |index| self.triangle(index).hit(r))
+      .map_or(0.0, |(_, hit)| {
+        solid_angle_pdf(1.0 / self.area(), origin, &hit)
+      })
+  }
+
+  pub fn triangle_count(&self) -> usize {
+    self.indices.len() / 3
+  }
+
+  pub fn triangle(&self, index: usize) -> Triangle {
+    let i = &self.indices[index * 3..index * 3 + 3];
+    Triangle::new(
+      self.positions[i[0] as usize],
+      self.positions[i[1] as usize],
+      self.positions[i[2] as usize],
+    )
+  }
+
+  fn shading_normal(&self, index: usize, u: f32, v: f32) -> Vector3 {
+    let i = &self.indices[index * 3..index * 3 + 3];
+    let n = (1.0 - u - v) * self.normals[i[0] as usize]
+      + u * self.normals[i[1] as usize]
+      + v * self.normals[i[2] as usize];
+    n.as_unit()
+  }
+
+  fn texcoord(&self, index: usize, u: f32, v: f32) -> (f32, f32) {
+    let i = &self.indices[index * 3..index * 3 + 3];
+    let (a, b, c) = (
+      self.texcoords[i[0] as usize],
+      self.texcoords[i[1] as usize],
+      self.texcoords[i[2] as usize],
+    );
+    let w = 1.0 - u - v;
+    (w * a.0 + u * b.0 + v * c.0, w * a.1 + u * b.1 + v * c.1)
+  }
+}
+
+impl Bounded for Mesh {
+  fn bounds(&self) -> Aabb {
+    self.bvh.bounds()
+  }
+}
+
+impl Collidable<Ray> for Mesh {
+  type Output = Option<RayHit>;
+
+  fn hit(&self, r: Ray) -> Option<RayHit> {
+    self
+      .bvh
+      .hit_with(r, |index| self.triangle(index).hit(r))
+      .map(|(index, mut hit)| {
+        if !self.normals.is_empty() {
+          hit.normal = self.shading_normal(index, hit.u, hit.v);
+        }
+        if !self.texcoords.is_empty() {
+          let (u, v) = self.texcoord(index, hit.u, hit.v);
+          hit.u = u;
+          hit.v = v;
+        }
+        hit
+      })
+  }
+}
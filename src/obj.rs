@@ -0,0 +1,410 @@
+use super::{Geometry, Material, Mesh, Vector3};
+
+use std::{
+  collections::HashMap,
+  error, fmt,
+  fs::File,
+  io::{self, BufRead, BufReader},
+  path::{Path, PathBuf},
+  str::SplitWhitespace,
+  sync::Arc,
+};
+
+#[derive(Debug)]
+pub enum ObjError {
+  Io {
+    path: PathBuf,
+    error: io::Error,
+  },
+  Parse {
+    path: PathBuf,
+    line: usize,
+    message: String,
+  },
+}
+
+impl fmt::Display for ObjError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ObjError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+      ObjError::Parse {
+        path,
+        line,
+        message,
+      } => write!(f, "{}:{}: {}", path.display(), line, message),
+    }
+  }
+}
+
+impl error::Error for ObjError {}
+
+/// Position in a file being parsed, used to attach locations to errors.
+struct Location<'a> {
+  path: &'a Path,
+  line: usize,
+}
+
+impl<'a> Location<'a> {
+  fn error(&self, message: String) -> ObjError {
+    ObjError::Parse {
+      path: self.path.to_path_buf(),
+      line: self.line,
+      message,
+    }
+  }
+
+  fn parse_f32(&self, word: Option<&str>, what: &str) -> Result<f32, ObjError> {
+    let word = word.ok_or_else(|| self.error(format!("missing {}", what)))?;
+    match word.parse::<f32>() {
+      Ok(value) if value.is_finite() => Ok(value),
+      _ => Err(self.error(format!("invalid {} '{}'", what, word))),
+    }
+  }
+
+  fn parse_vector3(&self, words: &mut SplitWhitespace, what: &str) -> Result<Vector3, ObjError> {
+    Ok(Vector3::new(
+      self.parse_f32(words.next(), what)?,
+      self.parse_f32(words.next(), what)?,
+      self.parse_f32(words.next(), what)?,
+    ))
+  }
+
+  /// Resolves a one-based (or negative, relative) OBJ index against the
+  /// number of elements defined so far.
+  fn parse_index(&self, word: &str, count: usize, what: &str) -> Result<usize, ObjError> {
+    let index: i64 = word
+      .parse()
+      .map_err(|_| self.error(format!("invalid {} index '{}'", what, word)))?;
+    let resolved = if index > 0 {
+      index - 1
+    } else {
+      count as i64 + index
+    };
+
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+      Err(self.error(format!(
+        "{} index {} is out of range ({} defined)",
+        what, index, count
+      )))
+    } else {
+      Ok(resolved as usize)
+    }
+  }
+}
+
+fn open(path: &Path) -> Result<BufReader<File>, ObjError> {
+  File::open(path)
+    .map(BufReader::new)
+    .map_err(|error| ObjError::Io {
+      path: path.to_path_buf(),
+      error,
+    })
+}
+
+fn read_line(path: &Path, line: io::Result<String>) -> Result<String, ObjError> {
+  let mut line = line.map_err(|error| ObjError::Io {
+    path: path.to_path_buf(),
+    error,
+  })?;
+
+  if let Some(comment) = line.find('#') {
+    line.truncate(comment);
+  }
+  Ok(line)
+}
+
+/// The subset of an MTL material description that maps onto `Material`.
+struct MtlDescription {
+  diffuse: Vector3,
+  specular: Vector3,
+  emission: Vector3,
+  shininess: f32,
+  refractive_index: Option<f32>,
+  dissolve: f32,
+  illum: u32,
+}
+
+impl MtlDescription {
+  fn new() -> MtlDescription {
+    MtlDescription {
+      diffuse: Vector3::new(0.8, 0.8, 0.8),
+      specular: Vector3::zero(),
+      emission: Vector3::zero(),
+      shininess: 0.0,
+      refractive_index: None,
+      dissolve: 1.0,
+      illum: 2,
+    }
+  }
+
+  fn to_material(&self) -> Material {
+    let max = |v: Vector3| v.r().max(v.g()).max(v.b());
+
+    if max(self.emission) > 0.0 {
+      Material::emissive(self.emission)
+    } else if self.dissolve < 1.0 || self.illum == 4 || self.illum == 6 || self.illum == 7 {
+      // Illumination models 4, 6 and 7 are the refractive ones.
+      Material::dielectric(self.refractive_index.unwrap_or(1.5))
+    } else if self.illum == 3 || max(self.specular) > max(self.diffuse) {
+      // Map the Phong exponent onto roughness so that sharp highlights
+      // become polished metal.
+      let roughness = (2.0 / (self.shininess + 2.0)).sqrt();
+      Material::metal(self.specular, roughness)
+    } else {
+      Material::lambert(self.diffuse)
+    }
+  }
+}
+
+fn load_mtl(path: &Path, materials: &mut HashMap<String, Material>) -> Result<(), ObjError> {
+  let mut current: Option<(String, MtlDescription)> = None;
+
+  for (index, line) in open(path)?.lines().enumerate() {
+    let line = read_line(path, line)?;
+    let loc = Location {
+      path,
+      line: index + 1,
+    };
+
+    let mut words = line.split_whitespace();
+    let keyword = match words.next() {
+      Some(keyword) => keyword,
+      None => continue,
+    };
+
+    if keyword == "newmtl" {
+      let name = words
+        .next()
+        .ok_or_else(|| loc.error("missing material name".to_string()))?;
+      if let Some((name, description)) = current.take() {
+        materials.insert(name, description.to_material());
+      }
+      current = Some((name.to_string(), MtlDescription::new()));
+      continue;
+    }
+
+    let description = match current {
+      Some((_, ref mut description)) => description,
+      None => return Err(loc.error(format!("'{}' before any 'newmtl'", keyword))),
+    };
+
+    match keyword {
+      "Kd" => description.diffuse = loc.parse_vector3(&mut words, "diffuse colour")?,
+      "Ks" => description.specular = loc.parse_vector3(&mut words, "specular colour")?,
+      "Ke" => description.emission = loc.parse_vector3(&mut words, "emissive colour")?,
+      "Ns" => description.shininess = loc.parse_f32(words.next(), "specular exponent")?,
+      "Ni" => description.refractive_index = Some(loc.parse_f32(words.next(), "refractive index")?),
+      "d" => description.dissolve = loc.parse_f32(words.next(), "dissolve")?,
+      "Tr" => description.dissolve = 1.0 - loc.parse_f32(words.next(), "transparency")?,
+      "illum" => {
+        let word = words
+          .next()
+          .ok_or_else(|| loc.error("missing illumination model".to_string()))?;
+        description.illum = word
+          .parse()
+          .map_err(|_| loc.error(format!("invalid illumination model '{}'", word)))?;
+      },
+      // Texture maps and the remaining statements have no equivalent yet.
+      _ => (),
+    }
+  }
+
+  if let Some((name, description)) = current.take() {
+    materials.insert(name, description.to_material());
+  }
+
+  Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct VertexRef {
+  position: usize,
+  texcoord: Option<usize>,
+  normal: Option<usize>,
+}
+
+/// Faces sharing a group and material, which become one mesh unless they
+/// are all degenerate.
+struct Part {
+  material: Material,
+  faces: Vec<[VertexRef; 3]>,
+}
+
+impl Part {
+  fn to_geometry(
+    &self,
+    positions: &[Vector3],
+    normals: &[Vector3],
+    texcoords: &[(f32, f32)],
+  ) -> Option<Geometry> {
+    // Meshes hold attributes for every vertex or none, so drop an attribute
+    // if any face in the part omits it.
+    let vertices = || self.faces.iter().flat_map(|face| face.iter());
+    let has_normals = vertices().all(|v| v.normal.is_some());
+    let has_texcoords = vertices().all(|v| v.texcoord.is_some());
+
+    let mut lookup = HashMap::new();
+    let mut mesh_positions = vec![];
+    let mut mesh_normals = vec![];
+    let mut mesh_texcoords = vec![];
+    let mut indices = Vec::with_capacity(self.faces.len() * 3);
+
+    for v in vertices() {
+      let key = VertexRef {
+        position: v.position,
+        texcoord: if has_texcoords { v.texcoord } else { None },
+        normal: if has_normals { v.normal } else { None },
+      };
+
+      let index = *lookup.entry(key).or_insert_with(|| {
+        mesh_positions.push(positions[key.position]);
+        if let Some(normal) = key.normal {
+          mesh_normals.push(normals[normal]);
+        }
+        if let Some(texcoord) = key.texcoord {
+          mesh_texcoords.push(texcoords[texcoord]);
+        }
+        (mesh_positions.len() - 1) as u32
+      });
+      indices.push(index);
+    }
+
+    // The indices are all in range by construction, so the only way to fail
+    // is for every face to be degenerate, which leaves nothing to draw.
+    Mesh::try_new(mesh_positions, mesh_normals, mesh_texcoords, indices)
+      .ok()
+      .map(|mesh| Geometry::from_mesh(Arc::new(mesh), self.material))
+  }
+}
+
+/// Loads a Wavefront OBJ file and the MTL libraries it references, producing
+/// one mesh per group and material.
+pub fn load_obj(path: &Path) -> Result<Vec<Geometry>, ObjError> {
+  let base = path.parent().unwrap_or_else(|| Path::new(""));
+  let default_material = Material::lambert(Vector3::new(0.5, 0.5, 0.5));
+
+  let mut positions = vec![];
+  let mut normals = vec![];
+  let mut texcoords = vec![];
+  let mut materials = HashMap::new();
+  let mut parts = vec![];
+  let mut part = Part {
+    material: default_material,
+    faces: vec![],
+  };
+
+  for (index, line) in open(path)?.lines().enumerate() {
+    let line = read_line(path, line)?;
+    let loc = Location {
+      path,
+      line: index + 1,
+    };
+
+    let mut words = line.split_whitespace();
+    let keyword = match words.next() {
+      Some(keyword) => keyword,
+      None => continue,
+    };
+
+    match keyword {
+      "v" => positions.push(loc.parse_vector3(&mut words, "vertex position")?),
+      "vn" => {
+        let normal = loc.parse_vector3(&mut words, "vertex normal")?;
+        if normal == Vector3::zero() {
+          return Err(loc.error("zero-length vertex normal".to_string()));
+        }
+        normals.push(normal.as_unit());
+      },
+      "vt" => {
+        let u = loc.parse_f32(words.next(), "texture coordinate")?;
+        let v = match words.next() {
+          Some(word) => loc.parse_f32(Some(word), "texture coordinate")?,
+          None => 0.0,
+        };
+        texcoords.push((u, v));
+      },
+      "f" => {
+        let mut polygon = vec![];
+        for word in words {
+          let mut fields = word.split('/');
+          let position = match fields.next() {
+            Some(field) => loc.parse_index(field, positions.len(), "vertex")?,
+            None => return Err(loc.error(format!("invalid face vertex '{}'", word))),
+          };
+          let texcoord = match fields.next() {
+            Some(field) if !field.is_empty() => {
+              Some(loc.parse_index(field, texcoords.len(), "texture coordinate")?)
+            },
+            _ => None,
+          };
+          let normal = match fields.next() {
+            Some(field) if !field.is_empty() => {
+              Some(loc.parse_index(field, normals.len(), "normal")?)
+            },
+            _ => None,
+          };
+          if fields.next().is_some() {
+            return Err(loc.error(format!("invalid face vertex '{}'", word)));
+          }
+
+          polygon.push(VertexRef {
+            position,
+            texcoord,
+            normal,
+          });
+        }
+
+        if polygon.len() < 3 {
+          return Err(loc.error(format!(
+            "face has {} vertices, at least 3 are required",
+            polygon.len()
+          )));
+        }
+
+        // Triangulate as a fan, which is exact for the convex polygons
+        // exporters produce.
+        for i in 1..polygon.len() - 1 {
+          part.faces.push([polygon[0], polygon[i], polygon[i + 1]]);
+        }
+      },
+      "g" | "o" | "usemtl" => {
+        let material = if keyword == "usemtl" {
+          let name = words
+            .next()
+            .ok_or_else(|| loc.error("missing material name".to_string()))?;
+          *materials
+            .get(name)
+            .ok_or_else(|| loc.error(format!("undefined material '{}'", name)))?
+        } else {
+          part.material
+        };
+
+        if !part.faces.is_empty() {
+          parts.push(part);
+        }
+        part = Part {
+          material,
+          faces: vec![],
+        };
+      },
+      "mtllib" => {
+        for name in words {
+          load_mtl(&base.join(name), &mut materials)?;
+        }
+      },
+      // Smoothing groups, lines, points and free-form geometry are ignored.
+      _ => (),
+    }
+  }
+
+  if !part.faces.is_empty() {
+    parts.push(part);
+  }
+
+  Ok(
+    parts
+      .iter()
+      .filter_map(|part| part.to_geometry(&positions, &normals, &texcoords))
+      .collect(),
+  )
+}
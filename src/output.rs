@@ -0,0 +1,385 @@
+use super::{Aov, ColorSpace, Film, ToneMap, ToneMapping, Vector3};
+
+use std::{
+  fs::File,
+  io::{self, BufWriter, Write},
+  path::Path,
+  str::FromStr,
+};
+
+/// Precision of the channels written to OpenEXR files.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExrPixelType {
+  Half,
+  Float,
+}
+
+impl FromStr for ExrPixelType {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<ExrPixelType, String> {
+    match s {
+      "half" => Ok(ExrPixelType::Half),
+      "float" => Ok(ExrPixelType::Float),
+      _ => Err(format!(
+        "unknown EXR pixel type '{}', expected half or float",
+        s
+      )),
+    }
+  }
+}
+
+/// Converts `x` to the bits of the nearest IEEE 754 half precision value.
+fn to_half(x: f32) -> u16 {
+  let bits = x.to_bits();
+  let sign = ((bits >> 16) & 0x8000) as u16;
+  let exponent = ((bits >> 23) & 0xff) as i32;
+  let mantissa = bits & 0x7f_ffff;
+
+  if exponent == 0xff {
+    // Infinity stays infinite and NaN stays NaN.
+    let nan = if mantissa != 0 { 0x200 } else { 0 };
+    return sign | 0x7c00 | nan;
+  }
+
+  let half_exponent = exponent - 127 + 15;
+  if half_exponent >= 0x1f {
+    return sign | 0x7c00;
+  }
+
+  // Values too small for a normal half become subnormal, with the implicit
+  // leading bit made explicit and shifted into the mantissa.
+  let (significand, shift, base) = if half_exponent <= 0 {
+    if half_exponent < -10 {
+      return sign;
+    }
+    (mantissa | 0x80_0000, (14 - half_exponent) as u32, 0)
+  } else {
+    (mantissa, 13, (half_exponent as u32) << 10)
+  };
+
+  // Round to nearest, ties to even. A carry out of the mantissa correctly
+  // moves on to the next exponent, or to infinity.
+  let truncated = significand >> shift;
+  let round = 1 << (shift - 1);
+  let sticky = significand & (round - 1);
+  let rounded = if significand & round != 0 && (sticky != 0 || truncated & 1 != 0) {
+    truncated + 1
+  } else {
+    truncated
+  };
+  sign | (base + rounded) as u16
+}
+
+/// Writes an OpenEXR header attribute.
+fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+  header.extend_from_slice(name.as_bytes());
+  header.push(0);
+  header.extend_from_slice(kind.as_bytes());
+  header.push(0);
+  header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+  header.extend_from_slice(value);
+}
+
+/// CIE xy chromaticities of the red, green and blue primaries and the white
+/// point of `space`.
+fn chromaticities(space: ColorSpace) -> [f32; 8] {
+  match space {
+    ColorSpace::Srgb => [0.64, 0.33, 0.30, 0.60, 0.15, 0.06, 0.3127, 0.3290],
+    ColorSpace::Rec2020 => [0.708, 0.292, 0.170, 0.797, 0.131, 0.046, 0.3127, 0.3290],
+    ColorSpace::AcesCg => [0.713, 0.293, 0.165, 0.830, 0.128, 0.044, 0.32168, 0.33767],
+  }
+}
+
+/// Writes uncompressed scanline OpenEXR with a layer for each film in
+/// `layers`, whose named channels are taken from the components of its
+/// pixels in order. Layers named `""` give plain channel names like `R`,
+/// other names give `name.R` and so on. All films must be the same size,
+/// and the colour space of the first is recorded in the file.
+pub fn write_exr(
+  path: &Path,
+  layers: &[(&str, &[&str], &Film)],
+  pixel_type: ExrPixelType,
+) -> io::Result<()> {
+  let first = layers[0].2;
+  let (width, height) = (first.width, first.height);
+
+  // Readers expect channels sorted by name.
+  let mut channels = vec![];
+  for &(layer, names, film) in layers {
+    assert!(
+      film.width == width && film.height == height,
+      "EXR layers must match in size"
+    );
+    for (index, name) in names.iter().enumerate() {
+      let name = if layer.is_empty() {
+        name.to_string()
+      } else {
+        format!("{}.{}", layer, name)
+      };
+      channels.push((name, film, index));
+    }
+  }
+  channels.sort_by(|a, b| a.0.cmp(&b.0));
+
+  let (type_code, channel_size) = match pixel_type {
+    ExrPixelType::Half => (1i32, 2),
+    ExrPixelType::Float => (2i32, 4),
+  };
+
+  let mut header = vec![];
+  header.extend_from_slice(&0x0131_2f76u32.to_le_bytes());
+  header.extend_from_slice(&2u32.to_le_bytes());
+
+  let mut list = vec![];
+  for (name, _, _) in &channels {
+    list.extend_from_slice(name.as_bytes());
+    list.push(0);
+    list.extend_from_slice(&type_code.to_le_bytes());
+    // Perceptual flag and reserved bytes, then the sampling rates.
+    list.extend_from_slice(&[0, 0, 0, 0]);
+    list.extend_from_slice(&1i32.to_le_bytes());
+    list.extend_from_slice(&1i32.to_le_bytes());
+  }
+  list.push(0);
+  attribute(&mut header, "channels", "chlist", &list);
+
+  let floats = |values: &[f32]| -> Vec<u8> {
+    values
+      .iter()
+      .flat_map(|v| v.to_le_bytes().to_vec())
+      .collect()
+  };
+  let window: Vec<u8> = [0, 0, width as i32 - 1, height as i32 - 1]
+    .iter()
+    .flat_map(|v| v.to_le_bytes().to_vec())
+    .collect();
+  let primaries = floats(&chromaticities(first.space));
+  attribute(&mut header, "chromaticities", "chromaticities", &primaries);
+  attribute(&mut header, "compression", "compression", &[0]);
+  attribute(&mut header, "dataWindow", "box2i", &window);
+  attribute(&mut header, "displayWindow", "box2i", &window);
+  attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+  attribute(&mut header, "pixelAspectRatio", "float", &floats(&[1.0]));
+  attribute(
+    &mut header,
+    "screenWindowCenter",
+    "v2f",
+    &floats(&[0.0, 0.0]),
+  );
+  attribute(&mut header, "screenWindowWidth", "float", &floats(&[1.0]));
+  header.push(0);
+
+  // Every scanline is its own block, located through the offset table.
+  let line_size = width * channels.len() * channel_size;
+  let table_end = header.len() + 8 * height;
+
+  let mut out = BufWriter::new(File::create(path)?);
+  out.write_all(&header)?;
+  for y in 0..height {
+    let offset = (table_end + y * (8 + line_size)) as u64;
+    out.write_all(&offset.to_le_bytes())?;
+  }
+
+  for y in 0..height {
+    out.write_all(&(y as i32).to_le_bytes())?;
+    out.write_all(&(line_size as i32).to_le_bytes())?;
+    for &(_, film, index) in &channels {
+      for pixel in &film.pixels[y * width..(y + 1) * width] {
+        match pixel_type {
+          ExrPixelType::Half => out.write_all(&to_half(pixel[index]).to_le_bytes())?,
+          ExrPixelType::Float => out.write_all(&pixel[index].to_le_bytes())?,
+        }
+      }
+    }
+  }
+
+  out.flush()
+}
+
+/// Shared exponent encoding of a colour, as used by Radiance files.
+fn to_rgbe(c: Vector3) -> [u8; 4] {
+  let max = c.r().max(c.g()).max(c.b());
+  if max < 1e-32 {
+    return [0, 0, 0, 0];
+  }
+
+  // The exponent that puts `max` in [0.5, 1), read from its bits.
+  let exponent = ((max.to_bits() >> 23) & 0xff) as i32 - 126;
+  let scale = ((8 - exponent) as f32).exp2();
+  let byte = |x: f32| (x.max(0.0) * scale) as u8;
+  [
+    byte(c.r()),
+    byte(c.g()),
+    byte(c.b()),
+    (exponent + 128) as u8,
+  ]
+}
+
+/// Pixels converted to linear sRGB, which is what readers of formats
+/// without colour space information assume.
+fn srgb_pixels(film: &Film) -> Vec<Vector3> {
+  let to_srgb = film.space.matrix_to_srgb();
+  film.pixels.iter().map(|c| to_srgb * *c).collect()
+}
+
+/// Writes an uncompressed Radiance RGBE (`.hdr`) image.
+pub fn write_hdr(path: &Path, film: &Film) -> io::Result<()> {
+  let mut out = BufWriter::new(File::create(path)?);
+  write!(
+    out,
+    "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+    film.height, film.width
+  )?;
+  for c in srgb_pixels(film) {
+    out.write_all(&to_rgbe(c))?;
+  }
+  out.flush()
+}
+
+/// Writes a little-endian colour Portable Float Map, whose rows run from
+/// the bottom of the image up.
+pub fn write_pfm(path: &Path, film: &Film) -> io::Result<()> {
+  let mut out = BufWriter::new(File::create(path)?);
+  write!(out, "PF\n{} {}\n-1.0\n", film.width, film.height)?;
+  let pixels = srgb_pixels(film);
+  for row in pixels.chunks(film.width).rev() {
+    for c in row {
+      for i in 0..3 {
+        out.write_all(&c[i].to_le_bytes())?;
+      }
+    }
+  }
+  out.flush()
+}
+
+/// Writes a tone mapped 8-bit image in any format the `image` crate knows.
+fn write_ldr(path: &Path, film: &Film, tone_mapping: ToneMapping) -> io::Result<()> {
+  let (width, height) = (film.width as u32, film.height as u32);
+  let buffer = film.to_rgb24(tone_mapping);
+  let mut imgbuf = image::RgbImage::new(width, height);
+
+  for (x, y, pixel) in imgbuf.enumerate_pixels_mut() {
+    let index = (y * width + x) as usize;
+    let value = buffer[index];
+    let r = (value >> 16) & 0xFF;
+    let g = (value >> 8) & 0xFF;
+    let b = value & 0xFF;
+
+    *pixel = image::Rgb([r as u8, g as u8, b as u8]);
+  }
+
+  imgbuf.save(path)
+}
+
+/// Lowercased extension of `path`, which decides the format it is saved in.
+fn extension(path: &Path) -> Option<String> {
+  path
+    .extension()
+    .and_then(|e| e.to_str())
+    .map(|e| e.to_lowercase())
+}
+
+/// Saves `film` in the format given by the extension of `path`. OpenEXR
+/// (`.exr`), Radiance (`.hdr`) and PFM (`.pfm`) keep the linear radiance
+/// as it is; anything else is tone mapped to 8 bits.
+pub fn save_film(
+  film: &Film,
+  path: &str,
+  tone_mapping: ToneMapping,
+  exr_pixel_type: ExrPixelType,
+) -> io::Result<()> {
+  println!("Writing {}x{} image to {}", film.width, film.height, path);
+  let path = Path::new(path);
+
+  match extension(path).as_deref() {
+    Some("exr") => write_exr(path, &[("", &["R", "G", "B"], film)], exr_pixel_type),
+    Some("hdr") => write_hdr(path, film),
+    Some("pfm") => write_pfm(path, film),
+    _ => write_ldr(path, film, tone_mapping),
+  }
+}
+
+/// Saves `film` with its AOVs. OpenEXR files get each AOV as a layer named
+/// after it; other formats save each to its own file next to `path`, like
+/// `image.albedo.png`. Data AOVs are visualized in 8-bit formats rather
+/// than tone mapped.
+pub fn save_render(
+  film: &Film,
+  aovs: &[(Aov, Film)],
+  path: &str,
+  tone_mapping: ToneMapping,
+  exr_pixel_type: ExrPixelType,
+) -> io::Result<()> {
+  let extension = extension(Path::new(path));
+  if extension.as_deref() == Some("exr") && !aovs.is_empty() {
+    println!(
+      "Writing {}x{} image with {} AOVs to {}",
+      film.width,
+      film.height,
+      aovs.len(),
+      path
+    );
+    let mut layers = vec![("", &["R", "G", "B"][..], film)];
+    for (aov, aov_film) in aovs {
+      layers.push((aov.name(), aov.channels(), aov_film));
+    }
+    return write_exr(Path::new(path), &layers, exr_pixel_type);
+  }
+
+  save_film(film, path, tone_mapping, exr_pixel_type)?;
+  let high_range = matches!(extension.as_deref(), Some("hdr") | Some("pfm"));
+  let file_stem = Path::new(path)
+    .file_stem()
+    .and_then(|s| s.to_str())
+    .unwrap_or("");
+  for (aov, aov_film) in aovs {
+    let name = match extension {
+      Some(ref extension) => format!("{}.{}.{}", file_stem, aov.name(), extension),
+      None => format!("{}.{}", file_stem, aov.name()),
+    };
+    let aov_path = Path::new(path).with_file_name(name);
+    let aov_path = aov_path.to_string_lossy();
+
+    if aov.is_color() || high_range {
+      save_film(aov_film, &aov_path, tone_mapping, exr_pixel_type)?;
+    } else {
+      let view = ToneMapping::new(ToneMap::Clamp, 0.0);
+      save_film(&aov.visualize(aov_film), &aov_path, view, exr_pixel_type)?;
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::f32;
+
+  #[test]
+  fn converts_to_half() {
+    assert_eq!(to_half(0.0), 0x0000);
+    assert_eq!(to_half(-0.0), 0x8000);
+    assert_eq!(to_half(1.0), 0x3c00);
+    assert_eq!(to_half(-2.0), 0xc000);
+    // The largest half, and values beyond it.
+    assert_eq!(to_half(65504.0), 0x7bff);
+    assert_eq!(to_half(65520.0), 0x7c00);
+    assert_eq!(to_half(1e6), 0x7c00);
+    assert_eq!(to_half(f32::NEG_INFINITY), 0xfc00);
+    assert_eq!(to_half(f32::NAN) & 0x7c00, 0x7c00);
+    assert_ne!(to_half(f32::NAN) & 0x3ff, 0);
+    // Subnormals, down to the smallest and to values rounding to zero.
+    assert_eq!(to_half(2.0f32.powi(-15)), 0x0200);
+    assert_eq!(to_half(2.0f32.powi(-24)), 0x0001);
+    assert_eq!(to_half(2.0f32.powi(-26)), 0x0000);
+    // Ties round to the even neighbour, for normals and subnormals alike.
+    assert_eq!(to_half(1.0 + 2.0f32.powi(-11)), 0x3c00);
+    assert_eq!(to_half(1.0 + 3.0 * 2.0f32.powi(-11)), 0x3c02);
+    assert_eq!(to_half(2.0f32.powi(-25)), 0x0000);
+    assert_eq!(to_half(3.0 * 2.0f32.powi(-25)), 0x0002);
+    // Rounding up out of the subnormals gives the smallest normal.
+    assert_eq!(to_half(2.0f32.powi(-14) * (1.0 - 2.0f32.powi(-12))), 0x0400);
+  }
+}
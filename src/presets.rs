@@ -0,0 +1,418 @@
+use super::{
+  Background, CameraSettings, Geometry, Material, Mesh, RenderSettings, Scene, Sphere, Triangle,
+  Vector3,
+};
+
+use std::{f32, fmt, sync::Arc};
+
+use rand::{distributions::Uniform, Rng, SeedableRng, XorShiftRng};
+
+const SEED: [u8; 16] = [16, 15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1];
+
+/// A built-in scene, with the camera and render settings it is meant to be
+/// seen with.
+pub struct Preset {
+  pub name: &'static str,
+  pub description: &'static str,
+  build: fn() -> (Scene, CameraSettings, RenderSettings),
+}
+
+impl Preset {
+  pub fn build(&self) -> (Scene, CameraSettings, RenderSettings) {
+    (self.build)()
+  }
+}
+
+impl fmt::Debug for Preset {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "Preset({})", self.name)
+  }
+}
+
+pub static PRESETS: [Preset; 6] = [
+  Preset {
+    name: "random",
+    description: "the random spheres from the cover of Ray Tracing in One Weekend",
+    build: random,
+  },
+  Preset {
+    name: "cornell",
+    description: "the Cornell box, lit only by the light in its ceiling",
+    build: cornell,
+  },
+  Preset {
+    name: "materials",
+    description: "rows of metals from polished to rough, diffuse colours and glasses",
+    build: materials,
+  },
+  Preset {
+    name: "texture-test",
+    description: "a checkerboard and converging stripes for judging filtering and aliasing",
+    build: texture_test,
+  },
+  Preset {
+    name: "next-week",
+    description: "the final scene of Ray Tracing: The Next Week, without its textures and media",
+    build: next_week,
+  },
+  Preset {
+    name: "caustics",
+    description: "glass spheres focusing a small, bright light onto the floor",
+    build: caustics,
+  },
+];
+
+/// Looks up a preset by name.
+pub fn find_preset(name: &str) -> Result<&'static Preset, String> {
+  PRESETS
+    .iter()
+    .find(|preset| preset.name == name)
+    .ok_or_else(|| {
+      let names: Vec<_> = PRESETS.iter().map(|preset| preset.name).collect();
+      format!(
+        "unknown preset '{}', expected one of {}",
+        name,
+        names.join(", ")
+      )
+    })
+}
+
+fn camera(eye: Vector3, target: Vector3, fov: f32) -> CameraSettings {
+  CameraSettings {
+    eye,
+    target,
+    up: Vector3::up(),
+    fov,
+    aperture: 0.0,
+    focus_distance: (eye - target).length(),
+    autofocus: None,
+  }
+}
+
+fn square(size: usize) -> RenderSettings {
+  RenderSettings {
+    width: Some(size),
+    height: Some(size),
+    ..RenderSettings::default()
+  }
+}
+
+fn sphere(center: Vector3, radius: f32, material: Material) -> Geometry {
+  Geometry::from_sphere(Sphere::new(center, radius), material)
+}
+
+/// The parallelogram spanned by `u` and `v` from `corner`, as two triangles.
+fn quad(corner: Vector3, u: Vector3, v: Vector3, material: Material) -> Vec<Geometry> {
+  vec![
+    Geometry::from_triangle(Triangle::new(corner, corner + u, corner + u + v), material),
+    Geometry::from_triangle(Triangle::new(corner, corner + u + v, corner + v), material),
+  ]
+}
+
+/// A box from the origin to `size`, turned by `angle` degrees about the
+/// vertical axis and then moved by `offset`.
+fn cuboid(size: Vector3, angle: f32, offset: Vector3, material: Material) -> Geometry {
+  let (sin, cos) = angle.to_radians().sin_cos();
+  let positions = (0..8)
+    .map(|corner| {
+      let pick = |bit: usize, extent: f32| if corner & bit != 0 { extent } else { 0.0 };
+      let (x, y, z) = (pick(1, size.x()), pick(2, size.y()), pick(4, size.z()));
+      Vector3::new(cos * x + sin * z, y, -sin * x + cos * z) + offset
+    })
+    .collect();
+  let faces: [[u32; 4]; 6] = [
+    [0, 1, 3, 2],
+    [4, 6, 7, 5],
+    [0, 4, 5, 1],
+    [2, 3, 7, 6],
+    [0, 2, 6, 4],
+    [1, 5, 7, 3],
+  ];
+  let indices = faces
+    .iter()
+    .flat_map(|f| vec![f[0], f[1], f[2], f[0], f[2], f[3]])
+    .collect();
+  Geometry::from_mesh(
+    Arc::new(Mesh::new(positions, vec![], vec![], indices)),
+    material,
+  )
+}
+
+fn random() -> (Scene, CameraSettings, RenderSettings) {
+  (
+    Scene::random(),
+    CameraSettings::default(),
+    RenderSettings::default(),
+  )
+}
+
+fn cornell() -> (Scene, CameraSettings, RenderSettings) {
+  let red = Material::lambert(Vector3::new(0.65, 0.05, 0.05));
+  let white = Material::lambert(Vector3::new(0.73, 0.73, 0.73));
+  let green = Material::lambert(Vector3::new(0.12, 0.45, 0.15));
+  let light = Material::emissive(Vector3::new(15.0, 15.0, 15.0));
+  let (x, y, z) = (
+    Vector3::new(555.0, 0.0, 0.0),
+    Vector3::new(0.0, 555.0, 0.0),
+    Vector3::new(0.0, 0.0, 555.0),
+  );
+
+  let mut items = vec![];
+  items.extend(quad(x, y, z, green));
+  items.extend(quad(Vector3::zero(), y, z, red));
+  items.extend(quad(Vector3::zero(), x, z, white));
+  items.extend(quad(y, x, z, white));
+  items.extend(quad(z, x, y, white));
+  items.extend(quad(
+    Vector3::new(213.0, 554.0, 227.0),
+    Vector3::new(130.0, 0.0, 0.0),
+    Vector3::new(0.0, 0.0, 105.0),
+    light,
+  ));
+  items.push(cuboid(
+    Vector3::new(165.0, 330.0, 165.0),
+    15.0,
+    Vector3::new(265.0, 0.0, 295.0),
+    white,
+  ));
+  items.push(cuboid(
+    Vector3::new(165.0, 165.0, 165.0),
+    -18.0,
+    Vector3::new(130.0, 0.0, 65.0),
+    white,
+  ));
+
+  let mut scene = Scene::new(items);
+  scene.background = Background::Black;
+  let camera = camera(
+    Vector3::new(278.0, 278.0, -800.0),
+    Vector3::new(278.0, 278.0, 0.0),
+    40.0,
+  );
+  (scene, camera, square(400))
+}
+
+fn materials() -> (Scene, CameraSettings, RenderSettings) {
+  let mut items = vec![sphere(
+    Vector3::new(0.0, -1000.0, 0.0),
+    1000.0,
+    Material::lambert(Vector3::new(0.5, 0.5, 0.5)),
+  )];
+
+  let row = |z: f32, materials: Vec<Material>| {
+    let start = -(materials.len() as f32 - 1.0);
+    materials
+      .into_iter()
+      .enumerate()
+      .map(|(i, material)| sphere(Vector3::new(start + 2.0 * i as f32, 0.8, z), 0.8, material))
+      .collect::<Vec<_>>()
+  };
+  let gold = Vector3::new(1.0, 0.78, 0.34);
+  items.extend(row(
+    -2.0,
+    [0.0, 0.1, 0.25, 0.5, 1.0]
+      .iter()
+      .map(|&roughness| Material::metal(gold, roughness))
+      .collect(),
+  ));
+  items.extend(row(
+    0.0,
+    vec![
+      Material::lambert(Vector3::new(0.8, 0.2, 0.2)),
+      Material::lambert(Vector3::new(0.2, 0.7, 0.3)),
+      Material::lambert(Vector3::new(0.2, 0.3, 0.8)),
+      Material::lambert(Vector3::new(0.9, 0.9, 0.9)),
+      Material::lambert(Vector3::new(0.05, 0.05, 0.05)),
+    ],
+  ));
+  items.extend(row(
+    2.0,
+    vec![
+      Material::dielectric(1.1),
+      Material::dielectric(1.33),
+      Material::dielectric(1.5),
+      Material::dielectric(2.4),
+      Material::emissive(Vector3::new(4.0, 3.0, 2.0)),
+    ],
+  ));
+
+  let camera = camera(
+    Vector3::new(0.0, 6.0, 11.0),
+    Vector3::new(0.0, 0.5, 0.0),
+    35.0,
+  );
+  (Scene::new(items), camera, RenderSettings::default())
+}
+
+fn texture_test() -> (Scene, CameraSettings, RenderSettings) {
+  let black = Material::lambert(Vector3::new(0.05, 0.05, 0.05));
+  let white = Material::lambert(Vector3::new(0.85, 0.85, 0.85));
+
+  // A checkerboard floor running off to the horizon.
+  let mut items = vec![];
+  let tiles = 40;
+  for i in 0..tiles {
+    for j in 0..tiles {
+      let corner = Vector3::new(
+        (i as f32 - tiles as f32 / 2.0) * 2.0,
+        0.0,
+        -(j as f32) * 2.0 + 4.0,
+      );
+      let material = if (i + j) % 2 == 0 { black } else { white };
+      items.extend(quad(
+        corner,
+        Vector3::new(2.0, 0.0, 0.0),
+        Vector3::new(0.0, 0.0, -2.0),
+        material,
+      ));
+    }
+  }
+
+  // Stripes converging on a point on an upright board, finer than a pixel
+  // towards the top.
+  let board = Vector3::new(-4.0, 1.0, -6.0);
+  items.extend(quad(
+    board,
+    Vector3::new(8.0, 0.0, 0.0),
+    Vector3::new(0.0, 5.0, 0.0),
+    white,
+  ));
+  let apex = board + Vector3::new(4.0, 5.0, 0.01);
+  let stripes = 48;
+  for i in 0..stripes {
+    let x = |i: f32| board.x() + 8.0 * i / stripes as f32;
+    let bottom = Vector3::new(x(i as f32), board.y(), board.z() + 0.01);
+    let next = Vector3::new(x(i as f32 + 0.5), board.y(), board.z() + 0.01);
+    items.push(Geometry::from_triangle(
+      Triangle::new(bottom, next, apex),
+      black,
+    ));
+  }
+
+  items.push(sphere(
+    Vector3::new(2.5, 1.0, -1.0),
+    1.0,
+    Material::metal(Vector3::new(0.9, 0.9, 0.9), 0.0),
+  ));
+
+  let camera = camera(
+    Vector3::new(0.0, 2.5, 8.0),
+    Vector3::new(0.0, 1.5, -4.0),
+    40.0,
+  );
+  (Scene::new(items), camera, RenderSettings::default())
+}
+
+fn next_week() -> (Scene, CameraSettings, RenderSettings) {
+  let mut rng = XorShiftRng::from_seed(SEED);
+  let unit = Uniform::new(0.0f32, 1.0f32);
+  let mut items = vec![];
+
+  let ground = Material::lambert(Vector3::new(0.48, 0.83, 0.53));
+  let boxes = 20;
+  for i in 0..boxes {
+    for j in 0..boxes {
+      let w = 100.0;
+      let corner = Vector3::new(-1000.0 + i as f32 * w, 0.0, -1000.0 + j as f32 * w);
+      let height = 1.0 + 100.0 * rng.sample(unit);
+      items.push(cuboid(Vector3::new(w, height, w), 0.0, corner, ground));
+    }
+  }
+
+  items.extend(quad(
+    Vector3::new(123.0, 554.0, 147.0),
+    Vector3::new(300.0, 0.0, 0.0),
+    Vector3::new(0.0, 0.0, 265.0),
+    Material::emissive(Vector3::new(7.0, 7.0, 7.0)),
+  ));
+
+  // The moving sphere, without its motion blur.
+  items.push(sphere(
+    Vector3::new(415.0, 400.0, 200.0),
+    50.0,
+    Material::lambert(Vector3::new(0.7, 0.3, 0.1)),
+  ));
+  items.push(sphere(
+    Vector3::new(260.0, 150.0, 45.0),
+    50.0,
+    Material::dielectric(1.5),
+  ));
+  items.push(sphere(
+    Vector3::new(0.0, 150.0, 145.0),
+    50.0,
+    Material::metal(Vector3::new(0.8, 0.8, 0.9), 1.0),
+  ));
+  // Glass in place of the glass sphere filled with blue fog.
+  items.push(sphere(
+    Vector3::new(360.0, 150.0, 145.0),
+    70.0,
+    Material::dielectric(1.5),
+  ));
+  // Plain colours in place of the Earth and the marble textures.
+  items.push(sphere(
+    Vector3::new(400.0, 200.0, 400.0),
+    100.0,
+    Material::lambert(Vector3::new(0.2, 0.35, 0.6)),
+  ));
+  items.push(sphere(
+    Vector3::new(220.0, 280.0, 300.0),
+    80.0,
+    Material::lambert(Vector3::new(0.8, 0.8, 0.8)),
+  ));
+
+  let white = Material::lambert(Vector3::new(0.73, 0.73, 0.73));
+  let (sin, cos) = 15f32.to_radians().sin_cos();
+  for _ in 0..1000 {
+    let (x, y, z) = (
+      165.0 * rng.sample(unit),
+      165.0 * rng.sample(unit),
+      165.0 * rng.sample(unit),
+    );
+    let center = Vector3::new(cos * x + sin * z, y, -sin * x + cos * z);
+    items.push(sphere(
+      center + Vector3::new(-100.0, 270.0, 395.0),
+      10.0,
+      white,
+    ));
+  }
+
+  let mut scene = Scene::new(items);
+  scene.background = Background::Black;
+  let camera = camera(
+    Vector3::new(478.0, 278.0, -600.0),
+    Vector3::new(278.0, 278.0, 0.0),
+    40.0,
+  );
+  (scene, camera, square(400))
+}
+
+fn caustics() -> (Scene, CameraSettings, RenderSettings) {
+  let items = vec![
+    sphere(
+      Vector3::new(0.0, -1000.0, 0.0),
+      1000.0,
+      Material::lambert(Vector3::new(0.75, 0.75, 0.75)),
+    ),
+    sphere(Vector3::new(-1.3, 1.0, 0.0), 1.0, Material::dielectric(1.5)),
+    sphere(Vector3::new(1.3, 0.8, 0.5), 0.8, Material::dielectric(1.33)),
+    sphere(
+      Vector3::new(-0.2, 0.4, 2.0),
+      0.4,
+      Material::metal(Vector3::new(0.95, 0.95, 0.95), 0.0),
+    ),
+    sphere(
+      Vector3::new(1.0, 5.0, -2.5),
+      0.25,
+      Material::emissive(Vector3::new(400.0, 380.0, 340.0)),
+    ),
+  ];
+
+  let mut scene = Scene::new(items);
+  scene.background = Background::Constant(Vector3::new(0.01, 0.01, 0.015));
+  let camera = camera(
+    Vector3::new(0.0, 3.0, 7.0),
+    Vector3::new(0.0, 0.6, 0.0),
+    35.0,
+  );
+  (scene, camera, RenderSettings::default())
+}
@@ -0,0 +1,393 @@
+use super::{seeded_rng, Geometry, Material, Sphere, Triangle, Vector3};
+
+use std::str::FromStr;
+
+use rand::{distributions::Uniform, Rng, SeedableRng, XorShiftRng};
+
+/// Seed of the spheres on the book's cover.
+const BOOK_SEED: [u8; 16] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16];
+
+/// Extent of the book's grid, which fits on its ground sphere.
+const BOOK_EXTENT: u32 = 11;
+
+/// Relative chances of each material among the small spheres.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaterialMix {
+  pub diffuse: f32,
+  pub metal: f32,
+  pub glass: f32,
+}
+
+impl Default for MaterialMix {
+  fn default() -> MaterialMix {
+    MaterialMix {
+      diffuse: 0.8,
+      metal: 0.15,
+      glass: 0.05,
+    }
+  }
+}
+
+impl MaterialMix {
+  /// Whether the weights are finite, none negative and not all zero.
+  fn is_valid(&self) -> bool {
+    let weights = [self.diffuse, self.metal, self.glass];
+    weights.iter().all(|w| w.is_finite() && *w >= 0.0) && weights.iter().sum::<f32>() > 0.0
+  }
+}
+
+impl FromStr for MaterialMix {
+  type Err = String;
+
+  /// Parses `diffuse,metal,glass` weights, such as `0.8,0.15,0.05`.
+  fn from_str(s: &str) -> Result<MaterialMix, String> {
+    let error = || format!("invalid material mix '{}', expected diffuse,metal,glass", s);
+    let weights = s
+      .split(',')
+      .map(|weight| weight.trim().parse::<f32>())
+      .collect::<Result<Vec<_>, _>>()
+      .map_err(|_| error())?;
+    match weights[..] {
+      [diffuse, metal, glass] => {
+        let mix = MaterialMix {
+          diffuse,
+          metal,
+          glass,
+        };
+        if mix.is_valid() {
+          Ok(mix)
+        } else {
+          Err(error())
+        }
+      },
+      _ => Err(error()),
+    }
+  }
+}
+
+/// Whether `min` to `max` is a range of finite, positive radii.
+fn is_valid_radius_range((min, max): (f32, f32)) -> bool {
+  min > 0.0 && min <= max && max.is_finite()
+}
+
+/// Parses a sphere radius `r`, or a range `min,max` to choose radii from.
+pub fn parse_radius_range(s: &str) -> Result<(f32, f32), String> {
+  let error = || format!("invalid radius '{}', expected <radius> or <min>,<max>", s);
+  let radii = s
+    .split(',')
+    .map(|radius| radius.trim().parse::<f32>())
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(|_| error())?;
+  let range = match radii[..] {
+    [radius] => (radius, radius),
+    [min, max] => (min, max),
+    _ => return Err(error()),
+  };
+  if is_valid_radius_range(range) {
+    Ok(range)
+  } else {
+    Err(error())
+  }
+}
+
+/// Settings for the field of small random spheres from the cover of Ray
+/// Tracing in One Weekend. The defaults give the book's scene exactly.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RandomSpheres {
+  /// Seed for the placement and materials of the spheres. Without one, the
+  /// spheres are those on the book's cover.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub seed: Option<u64>,
+  /// Spheres are scattered over the unit cells of a grid reaching this far
+  /// from the origin along x and z.
+  pub extent: u32,
+  /// Average count of spheres per grid cell.
+  pub density: f32,
+  pub mix: MaterialMix,
+  /// Smallest and largest radius of the small spheres.
+  pub radius: (f32, f32),
+  /// Whether to add the three large glass, diffuse and metal spheres.
+  pub heroes: bool,
+}
+
+impl Default for RandomSpheres {
+  fn default() -> RandomSpheres {
+    RandomSpheres {
+      seed: None,
+      extent: BOOK_EXTENT,
+      density: 1.0,
+      mix: MaterialMix::default(),
+      radius: (0.2, 0.2),
+      heroes: true,
+    }
+  }
+}
+
+impl RandomSpheres {
+  /// Checks the settings by the same rules as the command line options.
+  pub fn validate(&self) -> Result<(), String> {
+    if !self.mix.is_valid() {
+      return Err(format!(
+        "invalid material mix (diffuse: {}, metal: {}, glass: {}), expected weights that are \
+         not negative and not all zero",
+        self.mix.diffuse, self.mix.metal, self.mix.glass
+      ));
+    }
+    if !is_valid_radius_range(self.radius) {
+      return Err(format!(
+        "invalid radius range {:?}, expected positive radii with the smallest first",
+        self.radius
+      ));
+    }
+    if !(self.density >= 0.0 && self.density.is_finite()) {
+      return Err(format!(
+        "invalid density {}, expected a number that is not negative",
+        self.density
+      ));
+    }
+    Ok(())
+  }
+
+  fn rng(&self) -> XorShiftRng {
+    match self.seed {
+      None => XorShiftRng::from_seed(BOOK_SEED),
+      Some(seed) => seeded_rng(seed),
+    }
+  }
+
+  /// The ground, the small spheres and any hero spheres. The ground is the
+  /// book's sphere while the grid is no wider than the book's; beyond that,
+  /// spheres far out would float above its curve, so it is a flat square.
+  pub fn generate(&self) -> Vec<Geometry> {
+    let mut items = vec![];
+
+    let mut rng = self.rng();
+    let dist = Uniform::new(0.0f32, 1.0f32);
+
+    let ground = Material::lambert(Vector3::new(0.5, 0.5, 0.5));
+    if self.extent <= BOOK_EXTENT {
+      items.push(Geometry::from_sphere(
+        Sphere::new(Vector3::new(0.0, -1000.0, 0.0), 1000.0),
+        ground,
+      ));
+    } else {
+      let size = 2.0 * (self.extent + 1) as f32;
+      let corner = |x: f32, z: f32| Vector3::new(x * size, 0.0, z * size);
+      let (a, b, c, d) = (
+        corner(-1.0, -1.0),
+        corner(1.0, -1.0),
+        corner(1.0, 1.0),
+        corner(-1.0, 1.0),
+      );
+      items.push(Geometry::from_triangle(Triangle::new(a, c, b), ground));
+      items.push(Geometry::from_triangle(Triangle::new(a, d, c), ground));
+    }
+
+    let total = self.mix.diffuse + self.mix.metal + self.mix.glass;
+    let diffuse = self.mix.diffuse / total;
+    let metal = (self.mix.diffuse + self.mix.metal) / total;
+    let (whole, fraction) = (self.density.trunc() as usize, self.density.fract());
+    let extent = self.extent as i64;
+
+    for a in -extent..=extent {
+      for b in -extent..=extent {
+        let count = if fraction > 0.0 && rng.sample(dist) < fraction {
+          whole + 1
+        } else {
+          whole
+        };
+
+        for _ in 0..count {
+          let choose_mat = rng.sample(dist);
+          let (x, z) = (
+            a as f32 + 0.9 * rng.sample(dist),
+            b as f32 + 0.9 * rng.sample(dist),
+          );
+          let (min, max) = self.radius;
+          let radius = if min < max {
+            min + (max - min) * rng.sample(dist)
+          } else {
+            min
+          };
+          let center = Vector3::new(x, radius, z);
+
+          // As in the book, only the metal hero clears a space around it.
+          if self.heroes && (center - Vector3::new(4.0, radius, 0.0)).length() <= 0.9 {
+            continue;
+          }
+
+          let material = if choose_mat < diffuse {
+            Material::lambert(Vector3::new(
+              rng.sample(dist) * rng.sample(dist),
+              rng.sample(dist) * rng.sample(dist),
+              rng.sample(dist) * rng.sample(dist),
+            ))
+          } else if choose_mat < metal {
+            Material::metal(
+              Vector3::new(
+                0.5 * (1.0 + rng.sample(dist)),
+                0.5 * (1.0 + rng.sample(dist)),
+                0.5 * (1.0 + rng.sample(dist)),
+              ),
+              0.5 * rng.sample(dist),
+            )
+          } else {
+            Material::dielectric(1.5)
+          };
+
+          items.push(Geometry::from_sphere(Sphere::new(center, radius), material));
+        }
+      }
+    }
+
+    if self.heroes {
+      items.push(Geometry::from_sphere(
+        Sphere::new(Vector3::new(0.0, 1.0, 0.0), 1.0),
+        Material::dielectric(1.5),
+      ));
+
+      items.push(Geometry::from_sphere(
+        Sphere::new(Vector3::new(-4.0, 1.0, 0.0), 1.0),
+        Material::lambert(Vector3::new(0.4, 0.2, 0.1)),
+      ));
+
+      items.push(Geometry::from_sphere(
+        Sphere::new(Vector3::new(4.0, 1.0, 0.0), 1.0),
+        Material::metal(Vector3::new(0.7, 0.6, 0.5), 0.1),
+      ));
+    }
+
+    items
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::{Primitive, SceneItem};
+  use super::*;
+
+  #[test]
+  fn parses_material_mixes() {
+    assert_eq!(
+      "0.8,0.15,0.05".parse::<MaterialMix>(),
+      Ok(MaterialMix::default())
+    );
+    assert_eq!(
+      "0, 1, 1".parse::<MaterialMix>(),
+      Ok(MaterialMix {
+        diffuse: 0.0,
+        metal: 1.0,
+        glass: 1.0,
+      })
+    );
+    for bad in &["", "1,1", "1,1,1,1", "1,x,1", "0,0,0", "-1,1,1", "1,inf,1"] {
+      assert!(bad.parse::<MaterialMix>().is_err(), "accepted '{}'", bad);
+    }
+  }
+
+  #[test]
+  fn parses_radius_ranges() {
+    assert_eq!(parse_radius_range("0.2"), Ok((0.2, 0.2)));
+    assert_eq!(parse_radius_range("0.1, 0.3"), Ok((0.1, 0.3)));
+    for bad in &["", "0", "-0.2", "0.3,0.1", "0.1,0.2,0.3", "0.1,inf", "nan"] {
+      assert!(parse_radius_range(bad).is_err(), "accepted '{}'", bad);
+    }
+  }
+
+  #[test]
+  fn validates_settings() {
+    assert!(RandomSpheres::default().validate().is_ok());
+    let invalid = [
+      RandomSpheres {
+        mix: MaterialMix {
+          diffuse: 0.0,
+          metal: 0.0,
+          glass: 0.0,
+        },
+        ..RandomSpheres::default()
+      },
+      RandomSpheres {
+        radius: (-0.2, 0.2),
+        ..RandomSpheres::default()
+      },
+      RandomSpheres {
+        density: -1.0,
+        ..RandomSpheres::default()
+      },
+      RandomSpheres {
+        density: f32::NAN,
+        ..RandomSpheres::default()
+      },
+    ];
+    for spheres in &invalid {
+      assert!(spheres.validate().is_err(), "accepted {:?}", spheres);
+    }
+  }
+
+  #[test]
+  fn defaults_give_the_books_scene() {
+    // The book's generator, as it was written before it took settings.
+    let mut book = vec![];
+    let mut rng = XorShiftRng::from_seed(BOOK_SEED);
+    let dist = Uniform::new(0.0f32, 1.0f32);
+    book.push((
+      Vector3::new(0.0, -1000.0, 0.0),
+      1000.0,
+      Material::lambert(Vector3::new(0.5, 0.5, 0.5)),
+    ));
+    for a in -11..12 {
+      for b in -11..12 {
+        let choose_mat = rng.sample(dist);
+        let center = Vector3::new(
+          a as f32 + 0.9 * rng.sample(dist),
+          0.2,
+          b as f32 + 0.9 * rng.sample(dist),
+        );
+        if (center - Vector3::new(4.0, 0.2, 0.0)).length() > 0.9 {
+          let material = if choose_mat < 0.8 {
+            Material::lambert(Vector3::new(
+              rng.sample(dist) * rng.sample(dist),
+              rng.sample(dist) * rng.sample(dist),
+              rng.sample(dist) * rng.sample(dist),
+            ))
+          } else if choose_mat < 0.95 {
+            Material::metal(
+              Vector3::new(
+                0.5 * (1.0 + rng.sample(dist)),
+                0.5 * (1.0 + rng.sample(dist)),
+                0.5 * (1.0 + rng.sample(dist)),
+              ),
+              0.5 * rng.sample(dist),
+            )
+          } else {
+            Material::dielectric(1.5)
+          };
+          book.push((center, 0.2, material));
+        }
+      }
+    }
+    book.push((Vector3::new(0.0, 1.0, 0.0), 1.0, Material::dielectric(1.5)));
+    book.push((
+      Vector3::new(-4.0, 1.0, 0.0),
+      1.0,
+      Material::lambert(Vector3::new(0.4, 0.2, 0.1)),
+    ));
+    book.push((
+      Vector3::new(4.0, 1.0, 0.0),
+      1.0,
+      Material::metal(Vector3::new(0.7, 0.6, 0.5), 0.1),
+    ));
+
+    let generated: Vec<_> = RandomSpheres::default()
+      .generate()
+      .iter()
+      .map(|item| match item.primitive() {
+        Primitive::Sphere(sphere) => (sphere.center, sphere.radius, item.get_material()),
+        primitive => panic!("generated {:?} rather than a sphere", primitive),
+      })
+      .collect();
+    assert_eq!(generated, book);
+  }
+}
@@ -0,0 +1,376 @@
+use std::str::FromStr;
+
+use rand::{distributions::Uniform, Rng, SeedableRng, XorShiftRng};
+
+/// Largest `f32` below one, so that sample values stay within [0, 1).
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+/// Source of the uniform values consumed while tracing a path. Every pixel
+/// sample has its own, so no locking is needed and the values a pixel sees
+/// do not depend on how work is scheduled.
+///
+/// Each call draws the next dimension of the sample. Low-discrepancy
+/// samplers spread the values of a dimension evenly across the samples of a
+/// pixel, and `next_2d` keeps the two values of a pair well distributed
+/// together.
+pub trait Sampler {
+  /// Next value uniformly distributed over [0, 1).
+  fn next_1d(&mut self) -> f32;
+
+  fn next_2d(&mut self) -> (f32, f32) {
+    let u = self.next_1d();
+    (u, self.next_1d())
+  }
+}
+
+/// Scrambles the bits of `x` (the SplitMix64 finalizer), so that nearby
+/// inputs give unrelated outputs.
+pub fn mix(x: u64) -> u64 {
+  let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+  z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+  z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+  z ^ (z >> 31)
+}
+
+/// Random number generator seeded by `seed`, spread over all of its state.
+pub fn seeded_rng(seed: u64) -> XorShiftRng {
+  let mut bytes = [0; 16];
+  bytes[..8].copy_from_slice(&mix(seed).to_le_bytes());
+  bytes[8..].copy_from_slice(&mix(!seed).to_le_bytes());
+  XorShiftRng::from_seed(bytes)
+}
+
+/// Key identifying a pixel within a render using `seed`.
+fn pixel_key(seed: u64, x: usize, y: usize) -> u64 {
+  mix(mix(mix(seed) ^ x as u64) ^ y as u64)
+}
+
+/// Seed for one pixel sample, so that every pixel and sample draws an
+/// independent sequence that depends only on its position and the render
+/// `seed`.
+pub fn pixel_seed(seed: u64, x: usize, y: usize, sample: usize) -> u64 {
+  mix(pixel_key(seed, x, y) ^ sample as u64)
+}
+
+/// Maps the high bits of `x` to [0, 1).
+fn to_unit(x: u32) -> f32 {
+  (x >> 8) as f32 / (1u32 << 24) as f32
+}
+
+/// Independent pseudo-random numbers from a seeded xorshift generator.
+pub struct RandomSampler {
+  rng: XorShiftRng,
+  uniform: Uniform<f32>,
+}
+
+impl RandomSampler {
+  pub fn new(seed: u64) -> RandomSampler {
+    RandomSampler {
+      rng: seeded_rng(seed),
+      uniform: Uniform::new(0.0f32, 1.0f32),
+    }
+  }
+}
+
+impl Sampler for RandomSampler {
+  fn next_1d(&mut self) -> f32 {
+    self.rng.sample(self.uniform)
+  }
+}
+
+/// Pseudo-random permutation of `i` within [0, `len`) chosen by `key`
+/// (Kensler, "Correlated Multi-Jittered Sampling").
+fn permute(mut i: u32, len: u32, key: u32) -> u32 {
+  let mut w = len - 1;
+  w |= w >> 1;
+  w |= w >> 2;
+  w |= w >> 4;
+  w |= w >> 8;
+  w |= w >> 16;
+
+  loop {
+    i ^= key;
+    i = i.wrapping_mul(0xe170_893d);
+    i ^= key >> 16;
+    i ^= (i & w) >> 4;
+    i ^= key >> 8;
+    i = i.wrapping_mul(0x0929_eb3f);
+    i ^= key >> 23;
+    i ^= (i & w) >> 1;
+    i = i.wrapping_mul(1 | key >> 27);
+    i = i.wrapping_mul(0x6935_fa69);
+    i ^= (i & w) >> 11;
+    i = i.wrapping_mul(0x74dc_b303);
+    i ^= (i & w) >> 2;
+    i = i.wrapping_mul(0x9e50_1cc3);
+    i ^= (i & w) >> 2;
+    i = i.wrapping_mul(0xc860_a3df);
+    i &= w;
+    i ^= i >> 5;
+    if i < len {
+      break;
+    }
+  }
+
+  (i.wrapping_add(key)) % len
+}
+
+/// Jittered samples, one per stratum of each dimension. Every dimension
+/// visits its strata in a different pseudo-random order so that dimensions
+/// stay uncorrelated.
+pub struct StratifiedSampler {
+  key: u64,
+  sample: u32,
+  samples: u32,
+  dimension: u64,
+  jitter: RandomSampler,
+}
+
+impl StratifiedSampler {
+  pub fn new(seed: u64, x: usize, y: usize, sample: usize, samples: usize) -> StratifiedSampler {
+    StratifiedSampler {
+      key: pixel_key(seed, x, y),
+      sample: sample as u32,
+      samples: samples.max(1) as u32,
+      dimension: 0,
+      jitter: RandomSampler::new(pixel_seed(seed, x, y, sample)),
+    }
+  }
+
+  /// Stratum of the current sample among `strata`, in the order chosen for
+  /// the next dimension. Samples beyond `samples` start a fresh pass over
+  /// the strata.
+  fn next_stratum(&mut self, strata: u32) -> u32 {
+    let pass = u64::from(self.sample / self.samples);
+    let key = mix(self.key ^ mix(self.dimension ^ (pass << 32))) as u32;
+    self.dimension += 1;
+    permute(self.sample % self.samples, strata, key)
+  }
+}
+
+impl Sampler for StratifiedSampler {
+  fn next_1d(&mut self) -> f32 {
+    let stratum = self.next_stratum(self.samples);
+    ((stratum as f32 + self.jitter.next_1d()) / self.samples as f32).min(ONE_MINUS_EPSILON)
+  }
+
+  fn next_2d(&mut self) -> (f32, f32) {
+    // The smallest grid with at least one cell per sample. When the sample
+    // count is not a product of the two sides some cells go unused, but as
+    // the cells are picked at random each is equally likely to be.
+    let columns = (self.samples as f32).sqrt().ceil() as u32;
+    let rows = self.samples.div_ceil(columns);
+    let cell = self.next_stratum(columns * rows);
+    let (du, dv) = self.jitter.next_2d();
+    (
+      (((cell % columns) as f32 + du) / columns as f32).min(ONE_MINUS_EPSILON),
+      (((cell / columns) as f32 + dv) / rows as f32).min(ONE_MINUS_EPSILON),
+    )
+  }
+}
+
+const PRIMES: [u32; 32] = [
+  2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+  101, 103, 107, 109, 113, 127, 131,
+];
+
+/// Mirrors the base `base` digits of `index` about the radix point, after
+/// permuting the digits at each position by `key`. Leading zeros are
+/// permuted as well, down to the precision of an `f32`.
+fn scrambled_radical_inverse(base: u32, mut index: u64, key: u64) -> f32 {
+  let inverse = 1.0 / f64::from(base);
+  let mut scale = inverse;
+  let mut result = 0.0;
+  let mut position = 0;
+  while scale > 1e-8 {
+    let digit = (index % u64::from(base)) as u32;
+    let digit = permute(digit, base, mix(key ^ position) as u32);
+    result += f64::from(digit) * scale;
+    index /= u64::from(base);
+    scale *= inverse;
+    position += 1;
+  }
+
+  (result as f32).min(ONE_MINUS_EPSILON)
+}
+
+/// The Halton sequence, one prime base per dimension, with the digits
+/// randomly permuted per pixel and dimension so that neighbouring pixels do
+/// not share a pattern. Dimensions beyond the available bases fall back to
+/// random values.
+pub struct HaltonSampler {
+  key: u64,
+  sample: u64,
+  dimension: usize,
+  fallback: RandomSampler,
+}
+
+impl HaltonSampler {
+  pub fn new(seed: u64, x: usize, y: usize, sample: usize) -> HaltonSampler {
+    HaltonSampler {
+      key: pixel_key(seed, x, y),
+      sample: sample as u64,
+      dimension: 0,
+      fallback: RandomSampler::new(pixel_seed(seed, x, y, sample)),
+    }
+  }
+}
+
+impl Sampler for HaltonSampler {
+  fn next_1d(&mut self) -> f32 {
+    let dimension = self.dimension;
+    self.dimension += 1;
+    if dimension >= PRIMES.len() {
+      return self.fallback.next_1d();
+    }
+
+    let key = mix(self.key ^ dimension as u64);
+    scrambled_radical_inverse(PRIMES[dimension], self.sample, key)
+  }
+}
+
+/// First dimension of the Sobol sequence, the base two radical inverse.
+fn sobol_0(index: u32) -> u32 {
+  index.reverse_bits()
+}
+
+/// Second dimension of the Sobol sequence, whose generator matrix is
+/// Pascal's triangle modulo two.
+fn sobol_1(mut index: u32) -> u32 {
+  let mut v = 1 << 31;
+  let mut result = 0;
+  while index != 0 {
+    if index & 1 != 0 {
+      result ^= v;
+    }
+    index >>= 1;
+    v ^= v >> 1;
+  }
+  result
+}
+
+/// Hash which only lets each bit depend on the bits below it (Laine and
+/// Karras, improved by Burley).
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+  x = x.wrapping_add(seed);
+  x ^= x.wrapping_mul(0x6c50_b47c);
+  x ^= x.wrapping_mul(0xb82f_1e52);
+  x ^= x.wrapping_mul(0xc7af_e638);
+  x ^= x.wrapping_mul(0x8d22_f6e6);
+  x
+}
+
+/// Owen scrambling: randomly flips each bit based on the bits above it,
+/// which keeps a (0, m, 2) net a net.
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+  laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+/// The first two dimensions of the Sobol sequence with Owen scrambling,
+/// following Burley's "Practical Hash-based Owen Scrambling". Each pair of
+/// dimensions scrambles and shuffles the sequence with its own seed, which
+/// keeps pairs well distributed while decorrelating them from each other.
+pub struct SobolSampler {
+  key: u64,
+  sample: u32,
+  dimension: u64,
+}
+
+impl SobolSampler {
+  pub fn new(seed: u64, x: usize, y: usize, sample: usize) -> SobolSampler {
+    SobolSampler {
+      key: pixel_key(seed, x, y),
+      sample: sample as u32,
+      dimension: 0,
+    }
+  }
+
+  fn next_seed(&mut self) -> u64 {
+    self.dimension += 1;
+    mix(self.key ^ self.dimension)
+  }
+}
+
+impl Sampler for SobolSampler {
+  fn next_1d(&mut self) -> f32 {
+    let seed = self.next_seed();
+    let index = nested_uniform_scramble(self.sample, seed as u32);
+    to_unit(nested_uniform_scramble(sobol_0(index), (seed >> 32) as u32))
+  }
+
+  fn next_2d(&mut self) -> (f32, f32) {
+    let seed = self.next_seed();
+    let index = nested_uniform_scramble(self.sample, seed as u32);
+    let (x_seed, y_seed) = ((seed >> 32) as u32, mix(seed) as u32);
+    (
+      to_unit(nested_uniform_scramble(sobol_0(index), x_seed)),
+      to_unit(nested_uniform_scramble(sobol_1(index), y_seed)),
+    )
+  }
+}
+
+/// Samplers selectable from the command line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SamplerKind {
+  Random,
+  Stratified,
+  Halton,
+  Sobol,
+}
+
+impl SamplerKind {
+  pub fn all() -> [SamplerKind; 4] {
+    [
+      SamplerKind::Random,
+      SamplerKind::Stratified,
+      SamplerKind::Halton,
+      SamplerKind::Sobol,
+    ]
+  }
+
+  pub fn name(self) -> &'static str {
+    match self {
+      SamplerKind::Random => "random",
+      SamplerKind::Stratified => "stratified",
+      SamplerKind::Halton => "halton",
+      SamplerKind::Sobol => "sobol",
+    }
+  }
+}
+
+impl FromStr for SamplerKind {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<SamplerKind, String> {
+    SamplerKind::all()
+      .iter()
+      .find(|kind| kind.name() == s)
+      .cloned()
+      .ok_or_else(|| {
+        format!(
+          "unknown sampler '{}', expected one of random, stratified, halton, sobol",
+          s
+        )
+      })
+  }
+}
+
+/// How the samples of a render are generated.
+#[derive(Debug, Clone, Copy)]
+pub struct Sampling {
+  pub kind: SamplerKind,
+  pub seed: u64,
+}
+
+impl Sampling {
+  /// Sampler for sample `sample` of the `samples` taken in pixel (`x`, `y`).
+  pub fn create(&self, x: usize, y: usize, sample: usize, samples: usize) -> Box<dyn Sampler> {
+    let seed = self.seed;
+    match self.kind {
+      SamplerKind::Random => Box::new(RandomSampler::new(pixel_seed(seed, x, y, sample))),
+      SamplerKind::Stratified => Box::new(StratifiedSampler::new(seed, x, y, sample, samples)),
+      SamplerKind::Halton => Box::new(HaltonSampler::new(seed, x, y, sample)),
+      SamplerKind::Sobol => Box::new(SobolSampler::new(seed, x, y, sample)),
+    }
+  }
+}
@@ -1,12 +1,12 @@
-use super::{Camera, Collidable, Geometry, Material, Ray, RayHit, Sphere, Vector3};
+use super::{
+  Accumulator, Aov, Background, Budget, Bvh, Camera, Collidable, ColorSpace, Geometry, Integrator,
+  Material, RandomSpheres, Ray, RayHit, Sampler, Sampling, Vector3,
+};
 
-use std::f32;
+use std::{collections::HashMap, f32, time::Instant};
 
-use rand::{distributions::Uniform, Rng, SeedableRng, XorShiftRng};
 use rayon::prelude::*;
 
-const SEED: [u8; 16] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16];
-
 pub trait SceneItem: Collidable<Ray> {
   fn get_material(&self) -> Material;
 }
@@ -14,151 +14,314 @@ pub trait SceneItem: Collidable<Ray> {
 #[derive(Clone)]
 pub struct Scene {
   pub items: Vec<Geometry>,
-  pub is_dirty: bool,
+  pub background: Background,
+  /// Colour space of every colour in the scene, and of rendered images.
+  pub space: ColorSpace,
+  bvh: Bvh,
+  /// Indices of the emissive items, sampled directly for lighting.
+  lights: Vec<usize>,
+  /// Index of each item's material among the distinct materials.
+  material_ids: Vec<usize>,
+}
+
+/// Direction towards an unoccluded point on a light, chosen by
+/// `Scene::sample_light`.
+#[derive(Debug, Clone, Copy)]
+pub struct LightSample {
+  pub direction: Vector3,
+  pub radiance: Vector3,
+  /// Solid angle density of `direction`, including the choice of light.
+  pub pdf: f32,
+}
+
+/// Indices of the items that emit light and have a surface to sample it
+/// from. Items without area, such as meshes of no triangles, can't be
+/// sampled and are never hit, so they are left out.
+fn emissive_items(items: &[Geometry]) -> Vec<usize> {
+  items
+    .iter()
+    .enumerate()
+    .filter(|(_, item)| item.get_material().emitted() != Vector3::zero() && item.area() > 0.0)
+    .map(|(index, _)| index)
+    .collect()
+}
+
+/// The kind of `material` and the bits of its numbers, which are the same
+/// for equal materials, so that scenes of many distinct materials can be
+/// told apart by hashing rather than comparing every pair.
+fn material_key(material: Material) -> (u8, [u32; 4]) {
+  // Adding zero turns -0 into 0, which compares equal to it.
+  let bits = |v: Vector3, w: f32| {
+    let [x, y, z]: [f32; 3] = v.into();
+    [x, y, z, w].map(|n| (n + 0.0).to_bits())
+  };
+  match material {
+    Material::Lambertian { albedo } => (0, bits(albedo, 0.0)),
+    Material::Metallic { albedo, roughness } => (1, bits(albedo, roughness)),
+    Material::Dielectric { refractive_index } => (2, bits(Vector3::zero(), refractive_index)),
+    Material::Emissive { emission } => (3, bits(emission, 0.0)),
+  }
+}
+
+fn material_ids(items: &[Geometry]) -> Vec<usize> {
+  let mut materials = HashMap::new();
+  items
+    .iter()
+    .map(|item| {
+      let next = materials.len();
+      *materials
+        .entry(material_key(item.get_material()))
+        .or_insert(next)
+    })
+    .collect()
 }
 
 impl Scene {
-  #[allow(dead_code)]
   pub fn new(items: Vec<Geometry>) -> Scene {
+    let bvh = Bvh::build(&items);
+    let lights = emissive_items(&items);
+    let material_ids = material_ids(&items);
     Scene {
       items,
-      is_dirty: true,
+      background: Background::sky(),
+      space: ColorSpace::Srgb,
+      bvh,
+      lights,
+      material_ids,
     }
   }
 
-  pub fn random() -> Scene {
-    let mut items = vec![];
+  /// Brings the BVH, lights and material ids up to date after `items` has
+  /// been changed directly.
+  pub fn rebuild(&mut self) {
+    self.bvh = Bvh::build(&self.items);
+    self.lights = emissive_items(&self.items);
+    self.material_ids = material_ids(&self.items);
+  }
 
-    let mut rng = XorShiftRng::from_seed(SEED);
-    let dist = Uniform::new(0.0f32, 1.0f32);
-
-    items.push(Geometry::from_sphere(
-      Sphere::new(Vector3::new(0.0, -1000.0, 0.0), 1000.0),
-      Material::lambert(Vector3::new(0.5, 0.5, 0.5)),
-    ));
-
-    for a in -11..12 {
-      for b in -11..12 {
-        let choose_mat = rng.sample(dist);
-        let center = Vector3::new(
-          a as f32 + 0.9 * rng.sample(dist),
-          0.2,
-          b as f32 + 0.9 * rng.sample(dist),
-        );
-        if (center - Vector3::new(4.0, 0.2, 0.0)).length() > 0.9 {
-          let material = if choose_mat < 0.8 {
-            Material::lambert(Vector3::new(
-              rng.sample(dist) * rng.sample(dist),
-              rng.sample(dist) * rng.sample(dist),
-              rng.sample(dist) * rng.sample(dist),
-            ))
-          } else if choose_mat < 0.95 {
-            Material::metal(
-              Vector3::new(
-                0.5 * (1.0 + rng.sample(dist)),
-                0.5 * (1.0 + rng.sample(dist)),
-                0.5 * (1.0 + rng.sample(dist)),
-              ),
-              0.5 * rng.sample(dist),
-            )
-          } else {
-            Material::dielectric(1.5)
-          };
+  /// Index of the material of item `index` among the distinct materials in
+  /// the scene, in order of first use.
+  pub fn material_id(&self, index: usize) -> usize {
+    self.material_ids[index]
+  }
 
-          items.push(Geometry::from_sphere(Sphere::new(center, 0.2), material));
-        }
-      }
+  /// Chooses a light at random and a direction from `point` towards it,
+  /// returning the sample if nothing blocks the way.
+  pub fn sample_light(&self, point: Vector3, sampler: &mut dyn Sampler) -> Option<LightSample> {
+    let lights = &self.lights;
+    if lights.is_empty() {
+      return None;
     }
 
-    items.push(Geometry::from_sphere(
-      Sphere::new(Vector3::new(0.0, 1.0, 0.0), 1.0),
-      Material::dielectric(1.5),
-    ));
+    let choice = sampler.next_1d();
+    let (u, v) = sampler.next_2d();
+    let index = lights[((choice * lights.len() as f32) as usize).min(lights.len() - 1)];
+    let light = &self.items[index];
 
-    items.push(Geometry::from_sphere(
-      Sphere::new(Vector3::new(-4.0, 1.0, 0.0), 1.0),
-      Material::lambert(Vector3::new(0.4, 0.2, 0.1)),
-    ));
-
-    items.push(Geometry::from_sphere(
-      Sphere::new(Vector3::new(4.0, 1.0, 0.0), 1.0),
-      Material::metal(Vector3::new(0.7, 0.6, 0.5), 0.1),
-    ));
+    let (direction, pdf) = light.sample_direction(point, u, v);
+    match self.hit(Ray::new(point, direction)) {
+      Some(ref shadow) if shadow.index == index && light.reaches_sample(shadow.hit.t) => {
+        let pdf = pdf / lights.len() as f32;
+        if pdf > 0.0 {
+          Some(LightSample {
+            direction,
+            radiance: light.get_material().emitted(),
+            pdf,
+          })
+        } else {
+          None
+        }
+      },
+      _ => None,
+    }
+  }
 
-    Scene {
-      items,
-      is_dirty: true,
+  /// Solid angle density with which `sample_light` chooses `direction` from
+  /// `origin` towards item `index`.
+  pub fn light_pdf(&self, index: usize, origin: Vector3, direction: Vector3) -> f32 {
+    let lights = &self.lights;
+    if lights.is_empty() {
+      return 0.0;
     }
+
+    self.items[index].pdf(origin, direction) / lights.len() as f32
   }
 
-  fn color(r: Ray, scene: &Scene, depth: u32) -> Vector3 {
-    if let Some(hit) = scene.hit(r) {
-      if depth < 50 {
-        let material = hit.item.get_material();
-        if let Some(bounce) = material.scatter(r, hit.hit.point, hit.hit.normal) {
-          bounce.attenuation * Scene::color(bounce.bounced, scene, depth + 1)
-        } else {
-          Vector3::zero()
-        }
-      } else {
-        Vector3::zero()
-      }
-    } else {
-      let direction = r.direction.as_unit();
-      let t = 0.5 * (direction.y() + 1.0);
-      Vector3::lerp(t, Vector3::one(), Vector3::new(0.5, 0.7, 1.0))
+  /// Converts every colour in the scene into `space`, in which it will then
+  /// be rendered.
+  pub fn convert_to(&mut self, space: ColorSpace) {
+    let m = self.space.matrix_to(space);
+    for item in &mut self.items {
+      let material = item.get_material().transform_colors(m);
+      item.set_material(material);
     }
+    self.background = self.background.transform_colors(m);
+    self.space = space;
   }
 
-  pub fn render(&self, camera: Camera, width: usize, height: usize, samples: usize) -> Vec<u32> {
-    let dist = Uniform::new(0.0f32, 1.0f32);
+  /// Appends `items` to the scene and rebuilds the BVH to include them.
+  pub fn extend(&mut self, items: Vec<Geometry>) {
+    self.items.extend(items);
+    self.rebuild();
+  }
 
-    let mut buffer = vec![0; width * height];
-    buffer
+  /// The random spheres from the cover of Ray Tracing in One Weekend.
+  pub fn random() -> Scene {
+    Scene::new(RandomSpheres::default().generate())
+  }
+
+  /// Adds one more sample to every active pixel of `accumulator` and its
+  /// AOVs. `samples` is the number of samples per pixel planned in total,
+  /// which stratified samplers divide their strata between.
+  pub fn render_pass(
+    &self,
+    camera: Camera,
+    integrator: &dyn Integrator,
+    sampling: Sampling,
+    samples: usize,
+    accumulator: &mut Accumulator,
+  ) {
+    let (width, height) = (accumulator.sum.width, accumulator.sum.height);
+    let aovs = &accumulator.aovs;
+    let active = &accumulator.active;
+    let split = aovs.iter().any(|aov| aov.is_light());
+    // Chunks can't be empty, so without AOVs every row is paired with an
+    // unused single value instead.
+    let mut no_aovs = vec![Vector3::zero(); height];
+    let (aov_sums, aov_row) = if aovs.is_empty() {
+      (&mut no_aovs, 1)
+    } else {
+      (&mut accumulator.aov_sums, width * aovs.len())
+    };
+
+    // Each active pixel's sample, as its offset from the pixel's centre in
+    // pixels, up and to the right, and the radiance it found.
+    let mut taken = vec![None; width * height];
+    taken
       .par_chunks_mut(width)
+      .zip(accumulator.stats.par_chunks_mut(width))
+      .zip(aov_sums.par_chunks_mut(aov_row))
       .enumerate()
-      .for_each(|(row, line)| {
-        for col in 0..width {
-          let mut c = Vector3::zero();
-          let mut rng = XorShiftRng::from_seed(SEED);
-          for _ in 0..samples {
-            let u = (col as f32 + rng.sample(dist)) / width as f32;
-            let v = ((height - row) as f32 + rng.sample(dist)) / height as f32;
-
-            let ray = camera.get_ray(u, v);
-            c += Scene::color(ray, &self, 0);
+      .for_each(|(row, ((line, stats), aov_line))| {
+        for (col, (taken, stats)) in line.iter_mut().zip(stats.iter_mut()).enumerate() {
+          if !active[row * width + col] {
+            continue;
           }
 
-          c /= samples as f32;
+          // Samplers depend only on the pixel and sample index, which keeps
+          // the image independent of how rows are scheduled.
+          let sample = stats.count;
+          let mut sampler = sampling.create(col, row, sample, samples);
+          let (du, dv) = sampler.next_2d();
+          let u = (col as f32 + du) / width as f32;
+          let v = ((height - row) as f32 + dv) / height as f32;
 
-          // gamma 2 adjustment
-          c = Vector3::new(c.r().sqrt(), c.g().sqrt(), c.b().sqrt());
+          let ray = camera.get_ray(u, v, &mut *sampler);
+          let hit = self.hit(ray);
+          let (direct, indirect) = if split {
+            integrator.radiance_split(self, ray, hit.as_ref(), &mut *sampler)
+          } else {
+            (
+              integrator.radiance(self, ray, hit.as_ref(), &mut *sampler),
+              Vector3::zero(),
+            )
+          };
+          let radiance = direct + indirect;
+          *taken = Some((du - 0.5, dv - 0.5, radiance));
+          stats.sum += radiance;
+          stats.squares += radiance * radiance;
+          stats.count += 1;
+          if aovs.is_empty() {
+            continue;
+          }
 
-          line[col] = c.to_rgb24();
+          let pixel = &mut aov_line[col * aovs.len()..(col + 1) * aovs.len()];
+          for (aov, value) in aovs.iter().zip(pixel.iter_mut()) {
+            if !aov.is_averaged() && sample > 0 {
+              continue;
+            }
+            *value += match aov {
+              Aov::Direct => direct,
+              Aov::Indirect => indirect,
+              _ => aov.first_hit(self, ray, hit.as_ref()),
+            };
+          }
         }
       });
 
-    return buffer;
+    // Every pixel gathers the samples within the filter's reach, which is
+    // the same as each sample splatting into the pixels around it.
+    let filter = accumulator.filter;
+    let reach = filter.radius.ceil() as isize;
+    accumulator
+      .sum
+      .pixels
+      .par_chunks_mut(width)
+      .zip(accumulator.weights.par_chunks_mut(width))
+      .enumerate()
+      .for_each(|(row, (line, weights))| {
+        for (col, (sum, weight)) in line.iter_mut().zip(weights.iter_mut()).enumerate() {
+          for dy in -reach..=reach {
+            let source_row = row as isize + dy;
+            if source_row < 0 || source_row >= height as isize {
+              continue;
+            }
+            for dx in -reach..=reach {
+              let source_col = col as isize + dx;
+              if source_col < 0 || source_col >= width as isize {
+                continue;
+              }
+
+              let source = source_row as usize * width + source_col as usize;
+              if let Some((x, y, radiance)) = taken[source] {
+                // Rows count down the image, while offsets point up it.
+                let w = filter.weight(dx as f32 + x, y - dy as f32);
+                if w != 0.0 {
+                  *sum += radiance * w;
+                  *weight += w;
+                }
+              }
+            }
+          }
+        }
+      });
+
+    accumulator.passes += 1;
+  }
+
+  /// Takes passes into a fresh `accumulator` until `budget` is spent.
+  pub fn render(
+    &self,
+    camera: Camera,
+    integrator: &dyn Integrator,
+    sampling: Sampling,
+    budget: Budget,
+    accumulator: &mut Accumulator,
+  ) {
+    let start = Instant::now();
+    while accumulator.continues(&budget, start.elapsed()) {
+      self.render_pass(camera, integrator, sampling, budget.samples, accumulator);
+    }
   }
 }
 
 pub struct SceneRayHit {
-  hit: RayHit,
-  item: Geometry,
+  pub hit: RayHit,
+  /// Index of the item hit within `Scene::items`.
+  pub index: usize,
 }
 
-impl Collidable<Ray> for Scene {
-  type Output = Option<SceneRayHit>;
-
-  fn hit(&self, r: Ray) -> Option<SceneRayHit> {
+impl Scene {
+  #[cfg(test)]
+  fn hit_brute_force(&self, r: Ray) -> Option<SceneRayHit> {
     let mut result: Option<SceneRayHit> = None;
     let mut t = f32::MAX;
 
-    for item in self.items.iter() {
+    for (index, item) in self.items.iter().enumerate() {
       if let Some(hit) = item.hit(r) {
         if hit.t < t {
           t = hit.t;
-          result = Some(SceneRayHit { hit, item: *item })
+          result = Some(SceneRayHit { hit, index })
         }
       }
     }
@@ -167,8 +330,111 @@ impl Collidable<Ray> for Scene {
   }
 }
 
+impl Collidable<Ray> for Scene {
+  type Output = Option<SceneRayHit>;
+
+  fn hit(&self, r: Ray) -> Option<SceneRayHit> {
+    self
+      .bvh
+      .hit(&self.items, r)
+      .map(|(index, hit)| SceneRayHit { hit, index })
+  }
+}
+
 impl SceneItem for Scene {
   fn get_material(&self) -> Material {
     Material::lambert(Vector3::zero())
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::super::{Mesh, RandomSampler, Sphere, Triangle};
+  use super::*;
+
+  use std::sync::Arc;
+
+  use rand::{distributions::Uniform, Rng, SeedableRng, XorShiftRng};
+
+  const SEED: [u8; 16] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16];
+
+  #[test]
+  fn bvh_hits_match_brute_force() {
+    let scene = Scene::random();
+    let mut rng = XorShiftRng::from_seed(SEED);
+    let dist = Uniform::new(-1.0f32, 1.0f32);
+
+    for _ in 0..10_000 {
+      let origin = Vector3::new(
+        15.0 * rng.sample(dist),
+        1.0 + 3.0 * rng.sample(dist),
+        15.0 * rng.sample(dist),
+      );
+      let direction = Vector3::new(rng.sample(dist), rng.sample(dist), rng.sample(dist));
+      let r = Ray::new(origin, direction);
+
+      match (scene.hit(r), scene.hit_brute_force(r)) {
+        (Some(a), Some(b)) => {
+          assert_eq!(a.index, b.index);
+          assert_eq!(a.hit.t, b.hit.t);
+          assert_eq!(a.hit.point, b.hit.point);
+        },
+        (None, None) => (),
+        (a, b) => panic!(
+          "BVH hit {:?} but brute force hit {:?} for {:?}",
+          a.map(|h| h.hit.t),
+          b.map(|h| h.hit.t),
+          r
+        ),
+      }
+    }
+  }
+
+  #[test]
+  fn lights_without_area_are_not_sampled() {
+    let emissive = Material::emissive(Vector3::one());
+    let scene = Scene::new(vec![
+      Geometry::from_sphere(Sphere::new(Vector3::new(0.0, 1.0, 0.0), 0.0), emissive),
+      Geometry::from_triangle(
+        Triangle::new(Vector3::one(), Vector3::one(), Vector3::one()),
+        emissive,
+      ),
+    ]);
+
+    assert!(scene.lights.is_empty());
+    let mut sampler = RandomSampler::new(0);
+    assert!(scene.sample_light(Vector3::zero(), &mut sampler).is_none());
+  }
+
+  #[test]
+  fn mesh_lights_are_not_lit_through_themselves() {
+    // Two triangles of one mesh, one above the other: points sampled on the
+    // upper one are hidden from below by the lower one.
+    let mesh = Mesh::new(
+      vec![
+        Vector3::new(-1.0, 1.0, -1.0),
+        Vector3::new(1.0, 1.0, -1.0),
+        Vector3::new(0.0, 1.0, 1.0),
+        Vector3::new(-1.0, 2.0, -1.0),
+        Vector3::new(1.0, 2.0, -1.0),
+        Vector3::new(0.0, 2.0, 1.0),
+      ],
+      vec![],
+      vec![],
+      vec![0, 1, 2, 3, 4, 5],
+    );
+    let scene = Scene::new(vec![Geometry::from_mesh(
+      Arc::new(mesh),
+      Material::emissive(Vector3::one()),
+    )]);
+
+    let mut sampler = RandomSampler::new(0);
+    let samples: Vec<LightSample> = (0..1000)
+      .filter_map(|_| scene.sample_light(Vector3::zero(), &mut sampler))
+      .collect();
+    assert!(samples.len() > 300 && samples.len() < 700);
+    for sample in samples {
+      assert!((sample.direction.y() - 1.0).abs() < 1e-4);
+    }
+  }
+}
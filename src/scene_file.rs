@@ -0,0 +1,480 @@
+use super::{
+  load_obj, Background, CameraSettings, ColorSpace, Geometry, Material, Mesh, ObjError, Primitive,
+  RandomSpheres, Scene, SceneItem, Sphere, Triangle, Vector3,
+};
+
+use std::{
+  collections::BTreeMap,
+  error, fmt, fs, io,
+  path::{Path, PathBuf},
+  sync::Arc,
+};
+
+use ron::{extensions::Extensions, ser::PrettyConfig, Options};
+
+#[derive(Debug)]
+pub enum SceneFileError {
+  Io {
+    path: PathBuf,
+    error: io::Error,
+  },
+  Parse {
+    path: PathBuf,
+    line: usize,
+    column: usize,
+    message: String,
+  },
+  Obj(ObjError),
+}
+
+impl fmt::Display for SceneFileError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      SceneFileError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+      SceneFileError::Parse {
+        path,
+        line,
+        column,
+        message,
+      } => write!(f, "{}:{}:{}: {}", path.display(), line, column, message),
+      SceneFileError::Obj(error) => error.fmt(f),
+    }
+  }
+}
+
+impl error::Error for SceneFileError {}
+
+/// Image settings a scene file may give, for any not given on the command
+/// line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderSettings {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub width: Option<usize>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub height: Option<usize>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub samples: Option<usize>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub seed: Option<u64>,
+}
+
+impl RenderSettings {
+  /// Checks that any image size and sample count given are positive.
+  pub fn validate(&self) -> Result<(), String> {
+    let settings = [
+      ("width", self.width),
+      ("height", self.height),
+      ("samples", self.samples),
+    ];
+    for (name, value) in settings.iter() {
+      if *value == Some(0) {
+        return Err(format!("invalid {} 0, expected a positive number", name));
+      }
+    }
+    Ok(())
+  }
+}
+
+/// A shape in a scene file, with the name of its material.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Object {
+  Sphere {
+    center: Vector3,
+    radius: f32,
+    material: String,
+  },
+  Triangle {
+    a: Vector3,
+    b: Vector3,
+    c: Vector3,
+    material: String,
+  },
+  /// A triangle mesh given in full, three indices into `positions` per
+  /// triangle. `normals` and `texcoords` are per vertex, or empty.
+  Mesh {
+    positions: Vec<Vector3>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    normals: Vec<Vector3>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    texcoords: Vec<(f32, f32)>,
+    indices: Vec<u32>,
+    material: String,
+  },
+  /// The meshes of a Wavefront OBJ file, found relative to the scene file.
+  /// They keep the materials of its MTL files unless one is given.
+  Obj {
+    path: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    material: Option<String>,
+  },
+}
+
+/// A scene described in RON: the camera, the render settings, the
+/// background, any random spheres, named materials and the objects made of
+/// them. Lights are objects with an `Emissive` material. Colours are linear
+/// sRGB. Every section may be left out; a missing camera is the default
+/// view.
+///
+/// ```ron
+/// (
+///   camera: (eye: (13, 2, 3), target: (0, 0, 0), up: (0, 1, 0), fov: 20,
+///     aperture: 0.1, focus_distance: 10),
+///   render: (width: 400, height: 300, samples: 100),
+///   background: Gradient(bottom: (1, 1, 1), top: (0.5, 0.7, 1)),
+///   random_spheres: (seed: 7, extent: 20, density: 0.5, heroes: false),
+///   materials: {
+///     "ground": Lambertian(albedo: (0.5, 0.5, 0.5)),
+///     "lamp": Emissive(emission: (4, 4, 4)),
+///   },
+///   objects: [
+///     Sphere(center: (0, -1000, 0), radius: 1000, material: "ground"),
+///     Sphere(center: (0, 3, 0), radius: 0.5, material: "lamp"),
+///   ],
+/// )
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneFile {
+  #[serde(default)]
+  pub camera: CameraSettings,
+  #[serde(default)]
+  pub render: RenderSettings,
+  #[serde(default = "Background::sky")]
+  pub background: Background,
+  /// A field of spheres as on the cover of Ray Tracing in One Weekend,
+  /// generated with these settings, alongside the objects.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub random_spheres: Option<RandomSpheres>,
+  #[serde(default)]
+  pub materials: BTreeMap<String, Material>,
+  #[serde(default)]
+  pub objects: Vec<Object>,
+}
+
+/// One-based line and column of occurrence `nth`, counting from zero, of
+/// `needle` in `text`, or of its end if there is no such occurrence.
+fn locate(text: &str, needle: &str, nth: usize) -> (usize, usize) {
+  let offset = text
+    .match_indices(needle)
+    .nth(nth)
+    .map_or(text.len(), |(offset, _)| offset);
+  let before = &text[..offset];
+  let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+  (
+    before.matches('\n').count() + 1,
+    before[line_start..].chars().count() + 1,
+  )
+}
+
+impl SceneFile {
+  /// Describes `scene` as seen through `camera`. Materials are named after
+  /// their index among the scene's distinct materials, and meshes are
+  /// written out in full, so that the file stands on its own. Colours are
+  /// converted back to sRGB from the scene's colour space.
+  pub fn from_scene(scene: &Scene, camera: CameraSettings, render: RenderSettings) -> SceneFile {
+    let to_srgb = |material: Material| {
+      if scene.space == ColorSpace::Srgb {
+        material
+      } else {
+        material.transform_colors(scene.space.matrix_to(ColorSpace::Srgb))
+      }
+    };
+    let background = if scene.space == ColorSpace::Srgb {
+      scene.background
+    } else {
+      scene
+        .background
+        .transform_colors(scene.space.matrix_to(ColorSpace::Srgb))
+    };
+
+    // Padded so that the names sort in the order of the materials.
+    let count = (0..scene.items.len())
+      .map(|index| scene.material_id(index) + 1)
+      .max()
+      .unwrap_or(0);
+    let digits = count.to_string().len();
+    let name = |index: usize| format!("material-{:01$}", scene.material_id(index), digits);
+
+    let mut materials = BTreeMap::new();
+    let mut objects = vec![];
+    for (index, item) in scene.items.iter().enumerate() {
+      let material = name(index);
+      materials.insert(material.clone(), to_srgb(item.get_material()));
+      objects.push(match item.primitive() {
+        Primitive::Sphere(sphere) => Object::Sphere {
+          center: sphere.center,
+          radius: sphere.radius,
+          material,
+        },
+        Primitive::Triangle(triangle) => Object::Triangle {
+          a: triangle.a,
+          b: triangle.b,
+          c: triangle.c,
+          material,
+        },
+        Primitive::Mesh(mesh) => Object::Mesh {
+          positions: mesh.positions.clone(),
+          normals: mesh.normals.clone(),
+          texcoords: mesh.texcoords.clone(),
+          indices: mesh.indices.clone(),
+          material,
+        },
+      });
+    }
+
+    SceneFile {
+      camera,
+      render,
+      background,
+      random_spheres: None,
+      materials,
+      objects,
+    }
+  }
+
+  /// The description as RON, with every material and object on a line of
+  /// its own.
+  pub fn to_ron(&self) -> String {
+    let config = PrettyConfig::new()
+      .depth_limit(2)
+      .indentor("  ".to_string())
+      .extensions(Extensions::IMPLICIT_SOME);
+    let mut text =
+      ron::ser::to_string_pretty(self, config).expect("scene descriptions always serialize");
+    text.push('\n');
+    text
+  }
+
+  /// Builds the scene, loading any OBJ files relative to `path`. `text` is
+  /// the file's contents, for locating errors.
+  fn build(&self, path: &Path, text: &str) -> Result<Scene, SceneFileError> {
+    let error = |(line, column), message| SceneFileError::Parse {
+      path: path.to_path_buf(),
+      line,
+      column,
+      message,
+    };
+    let material = |name: &str| {
+      self.materials.get(name).cloned().ok_or_else(|| {
+        // Names only appear in the text as keys of `materials` and in the
+        // objects using them, so an unknown one is first found in an object.
+        let location = locate(text, &format!("\"{}\"", name), 0);
+        error(location, format!("unknown material '{}'", name))
+      })
+    };
+
+    self
+      .render
+      .validate()
+      .map_err(|message| error(locate(text, "render", 0), message))?;
+    let mut items = match self.random_spheres {
+      Some(spheres) => {
+        spheres
+          .validate()
+          .map_err(|message| error(locate(text, "random_spheres", 0), message))?;
+        spheres.generate()
+      },
+      None => vec![],
+    };
+    let mut meshes = 0;
+    for object in &self.objects {
+      match object {
+        Object::Sphere {
+          center,
+          radius,
+          material: name,
+        } => items.push(Geometry::from_sphere(
+          Sphere::new(*center, *radius),
+          material(name)?,
+        )),
+        Object::Triangle {
+          a,
+          b,
+          c,
+          material: name,
+        } => items.push(Geometry::from_triangle(
+          Triangle::new(*a, *b, *c),
+          material(name)?,
+        )),
+        Object::Mesh {
+          positions,
+          normals,
+          texcoords,
+          indices,
+          material: name,
+        } => {
+          let mesh = Mesh::try_new(
+            positions.clone(),
+            normals.clone(),
+            texcoords.clone(),
+            indices.clone(),
+          )
+          .map_err(|message| error(locate(text, "Mesh(", meshes), message))?;
+          meshes += 1;
+          items.push(Geometry::from_mesh(Arc::new(mesh), material(name)?));
+        },
+        Object::Obj {
+          path: obj_path,
+          material: name,
+        } => {
+          let obj_path = path
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(obj_path);
+          let mut meshes = load_obj(&obj_path).map_err(SceneFileError::Obj)?;
+          if let Some(name) = name {
+            let material = material(name)?;
+            for mesh in &mut meshes {
+              mesh.set_material(material);
+            }
+          }
+          items.extend(meshes);
+        },
+      }
+    }
+
+    let mut scene = Scene::new(items);
+    scene.background = self.background;
+    Ok(scene)
+  }
+}
+
+/// Parses `text`, the contents of the scene file at `path`, returning its
+/// description along with the scene built from it.
+fn parse_scene_file(path: &Path, text: &str) -> Result<(SceneFile, Scene), SceneFileError> {
+  // Optional settings can be written without wrapping them in `Some`.
+  let options = Options::default().with_default_extension(Extensions::IMPLICIT_SOME);
+  let file: SceneFile = options
+    .from_str(text)
+    .map_err(|error| SceneFileError::Parse {
+      path: path.to_path_buf(),
+      line: error.position.line,
+      column: error.position.col,
+      message: error.code.to_string(),
+    })?;
+
+  let scene = file.build(path, text)?;
+  Ok((file, scene))
+}
+
+/// Reads the scene file at `path`, returning its description along with the
+/// scene built from it.
+pub fn load_scene_file(path: &Path) -> Result<(SceneFile, Scene), SceneFileError> {
+  let text = fs::read_to_string(path).map_err(|error| SceneFileError::Io {
+    path: path.to_path_buf(),
+    error,
+  })?;
+  parse_scene_file(path, &text)
+}
+
+/// Writes `file` to `path` as RON.
+pub fn save_scene_file(file: &SceneFile, path: &Path) -> Result<(), SceneFileError> {
+  fs::write(path, file.to_ron()).map_err(|error| SceneFileError::Io {
+    path: path.to_path_buf(),
+    error,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::{Accumulator, Budget, IntegratorKind, SamplerKind, Sampling};
+  use super::*;
+
+  #[test]
+  fn round_trip_renders_identically() {
+    let mut scene = Scene::random();
+    let mesh = Mesh::new(
+      vec![
+        Vector3::new(-1.0, 0.0, -3.0),
+        Vector3::new(1.0, 0.0, -3.0),
+        Vector3::new(0.0, 2.0, -3.0),
+      ],
+      vec![],
+      vec![],
+      vec![0, 1, 2],
+    );
+    scene.extend(vec![
+      Geometry::from_mesh(Arc::new(mesh), Material::emissive(Vector3::one())),
+      Geometry::from_triangle(
+        Triangle::new(
+          Vector3::new(2.0, 0.0, -3.0),
+          Vector3::new(3.0, 0.0, -3.0),
+          Vector3::new(2.5, 1.0, -3.0),
+        ),
+        Material::metal(Vector3::new(0.9, 0.9, 0.9), 0.2),
+      ),
+    ]);
+    let camera = CameraSettings::default();
+
+    let file = SceneFile::from_scene(&scene, camera, RenderSettings::default());
+    let (parsed, loaded) = parse_scene_file(Path::new("random.ron"), &file.to_ron()).unwrap();
+    assert_eq!(parsed, file);
+
+    let (width, height) = (32, 24);
+    let render = |scene: &Scene| {
+      let mut accumulator = Accumulator::new(width, height, scene.space);
+      let sampling = Sampling {
+        kind: SamplerKind::Random,
+        seed: 0,
+      };
+      scene.render(
+        camera.build(width as f32 / height as f32),
+        &*IntegratorKind::Path.build(),
+        sampling,
+        Budget::fixed(2),
+        &mut accumulator,
+      );
+      accumulator.film().pixels
+    };
+    assert!(render(&scene) == render(&loaded));
+  }
+
+  #[test]
+  fn malformed_meshes_are_errors() {
+    let file = |indices: &str| {
+      format!(
+        "(
+  materials: {{ \"white\": Lambertian(albedo: (1, 1, 1)) }},
+  objects: [
+    Mesh(positions: [(0, 0, 0), (1, 0, 0), (0, 1, 0)], indices: [0, 1, 2], material: \"white\"),
+    Mesh(positions: [(0, 0, 0), (1, 0, 0), (0, 1, 0)], indices: {}, material: \"white\"),
+  ],
+)",
+        indices
+      )
+    };
+    let error = |indices: &str| match parse_scene_file(Path::new("mesh.ron"), &file(indices)) {
+      Err(SceneFileError::Parse {
+        line,
+        column,
+        message,
+        ..
+      }) => (line, column, message),
+      Err(error) => panic!("unexpected error {}", error),
+      Ok(_) => panic!("mesh with indices {} was accepted", indices),
+    };
+
+    let (line, column, message) = error("[0, 1]");
+    assert_eq!((line, column), (5, 5));
+    assert!(message.contains("multiple of three"), "{}", message);
+    let (_, _, message) = error("[0, 1, 7]");
+    assert!(message.contains("out of range"), "{}", message);
+    let (_, _, message) = error("[]");
+    assert!(message.contains("no triangles"), "{}", message);
+    let (line, _, message) = error("[0, 0, 1]");
+    assert_eq!(line, 5);
+    assert!(message.contains("no area"), "{}", message);
+  }
+
+  #[test]
+  fn empty_renders_are_errors() {
+    for render in &["width: 0", "height: 0", "samples: 0"] {
+      let text = format!("(\n  render: (width: 40, {}),\n)", render);
+      match parse_scene_file(Path::new("render.ron"), &text) {
+        Err(SceneFileError::Parse { line, .. }) => assert_eq!(line, 2),
+        Err(error) => panic!("unexpected error {}", error),
+        Ok(_) => panic!("render settings '{}' were accepted", render),
+      }
+    }
+  }
+}
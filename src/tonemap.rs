@@ -0,0 +1,172 @@
+use super::Vector3;
+
+use std::{fmt, str::FromStr};
+
+/// White point of extended Reinhard when none is given.
+const DEFAULT_WHITE: f32 = 4.0;
+
+/// Operators compressing linear radiance into the displayable [0, 1] range.
+/// All work on each channel separately.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToneMap {
+  /// Leaves radiance unchanged, so everything above one clips.
+  Clamp,
+  /// `c / (1 + c)`, which never quite reaches white.
+  Reinhard,
+  /// Reinhard rescaled so that radiance `white` and above maps to one.
+  ExtendedReinhard { white: f32 },
+  /// Narkowicz's fit of the ACES filmic curve.
+  Aces,
+  /// John Hable's filmic curve from Uncharted 2.
+  Hable,
+}
+
+/// Hable's curve before normalising to its white point.
+fn hable_partial(x: f32) -> f32 {
+  let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+  (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+}
+
+impl ToneMap {
+  /// Operators in the order the viewer cycles through them, with extended
+  /// Reinhard using `white`.
+  #[cfg_attr(not(feature = "window"), allow(dead_code))]
+  pub fn all(white: f32) -> [ToneMap; 5] {
+    [
+      ToneMap::Clamp,
+      ToneMap::Reinhard,
+      ToneMap::ExtendedReinhard { white },
+      ToneMap::Aces,
+      ToneMap::Hable,
+    ]
+  }
+
+  /// The operator after this one in `all`, wrapping around.
+  #[cfg_attr(not(feature = "window"), allow(dead_code))]
+  pub fn next(self, white: f32) -> ToneMap {
+    let all = ToneMap::all(white);
+    let index = all
+      .iter()
+      .position(|&op| op.name() == self.name())
+      .unwrap_or(0);
+    all[(index + 1) % all.len()]
+  }
+
+  pub fn name(self) -> &'static str {
+    match self {
+      ToneMap::Clamp => "clamp",
+      ToneMap::Reinhard => "reinhard",
+      ToneMap::ExtendedReinhard { .. } => "reinhard-extended",
+      ToneMap::Aces => "aces",
+      ToneMap::Hable => "hable",
+    }
+  }
+
+  fn map(self, x: f32) -> f32 {
+    let x = x.max(0.0);
+    match self {
+      ToneMap::Clamp => x.min(1.0),
+      ToneMap::Reinhard => x / (1.0 + x),
+      ToneMap::ExtendedReinhard { white } => x * (1.0 + x / (white * white)) / (1.0 + x),
+      ToneMap::Aces => {
+        // The fit expects radiance scaled down to match the reference
+        // curve's exposure.
+        let x = 0.6 * x;
+        (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+      },
+      ToneMap::Hable => {
+        let white = 11.2;
+        hable_partial(2.0 * x) / hable_partial(white)
+      },
+    }
+    .min(1.0)
+  }
+
+  pub fn apply(self, c: Vector3) -> Vector3 {
+    Vector3::new(self.map(c.r()), self.map(c.g()), self.map(c.b()))
+  }
+}
+
+impl fmt::Display for ToneMap {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ToneMap::ExtendedReinhard { white } => write!(f, "{}:{}", self.name(), white),
+      _ => write!(f, "{}", self.name()),
+    }
+  }
+}
+
+impl FromStr for ToneMap {
+  type Err = String;
+
+  /// Accepts `clamp`, `reinhard`, `reinhard-extended` or
+  /// `reinhard-extended:<white>`, `aces` and `hable`.
+  fn from_str(s: &str) -> Result<ToneMap, String> {
+    let mut parts = s.splitn(2, ':');
+    let name = parts.next().unwrap_or("");
+    let parameter = parts.next();
+
+    let op = match name {
+      "clamp" => ToneMap::Clamp,
+      "reinhard" => ToneMap::Reinhard,
+      "reinhard-extended" => {
+        let white = match parameter {
+          Some(white) => match white.parse::<f32>() {
+            Ok(white) if white > 0.0 && white.is_finite() => white,
+            _ => return Err(format!("invalid white point in '{}'", s)),
+          },
+          None => DEFAULT_WHITE,
+        };
+        return Ok(ToneMap::ExtendedReinhard { white });
+      },
+      "aces" => ToneMap::Aces,
+      "hable" => ToneMap::Hable,
+      _ => {
+        return Err(format!(
+          "unknown tone map '{}', expected one of clamp, reinhard, reinhard-extended, aces, hable",
+          s
+        ))
+      },
+    };
+
+    match parameter {
+      Some(_) => Err(format!("tone map '{}' takes no parameter", name)),
+      None => Ok(op),
+    }
+  }
+}
+
+/// How linear radiance is brought into display range.
+#[derive(Debug, Clone, Copy)]
+pub struct ToneMapping {
+  pub operator: ToneMap,
+  /// Exposure adjustment in stops, each doubling the radiance.
+  pub exposure: f32,
+  /// White point extended Reinhard returns to after cycling through the
+  /// other operators.
+  pub white: f32,
+}
+
+impl ToneMapping {
+  pub fn new(operator: ToneMap, exposure: f32) -> ToneMapping {
+    let white = match operator {
+      ToneMap::ExtendedReinhard { white } => white,
+      _ => DEFAULT_WHITE,
+    };
+    ToneMapping {
+      operator,
+      exposure,
+      white,
+    }
+  }
+
+  /// Switches to the next operator, as the viewer does.
+  #[cfg_attr(not(feature = "window"), allow(dead_code))]
+  pub fn next_operator(&mut self) {
+    self.operator = self.operator.next(self.white);
+  }
+
+  pub fn apply(&self, c: Vector3) -> Vector3 {
+    self.operator.apply(c * self.exposure.exp2())
+  }
+}